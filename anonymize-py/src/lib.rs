@@ -1,19 +1,19 @@
+#![allow(non_local_definitions)]
 use std::collections::HashMap;
-use std::hash::Hash;
-use std::rc::Rc;
 
 use anonymize_rs::anonymizer::flashtext_anonymizer::FlashTextAnonymizer;
 use anonymize_rs::anonymizer::ner_anonymizer::NerAnonymizer;
+use anonymize_rs::anonymizer::normalizer::Normalizer;
 use anonymize_rs::anonymizer::regex_anonymizer::RegexAnonymizer;
-use anonymize_rs::anonymizer::{Anonymizer, ReplaceResult};
-use anonymize_rs::config::{AnonymizePipelineConfig, AnonymizerConfig};
+use anonymize_rs::anonymizer::{Anonymizer, Detector, ReplaceResult};
 use pyo3::prelude::*;
-use pyo3::types::IntoPyDict;
-use pyo3::wrap_pyfunction;
+
+/// `(label, text, start, end, score)`
+type DetectedEntity = (String, String, usize, usize, f32);
 
 #[pymodule]
 #[pyo3(name = "anonymizers")]
-fn anonymizerrs(py: Python, m: &PyModule) -> PyResult<()> {
+fn anonymizerrs(_py: Python, m: &PyModule) -> PyResult<()> {
     #[pyclass]
     pub struct Ner {
         anonymizer_instance: NerAnonymizer,
@@ -41,6 +41,35 @@ fn anonymizerrs(py: Python, m: &PyModule) -> PyResult<()> {
             }
         }
 
+        #[staticmethod]
+        pub fn from_dir(model_dir: String, entities: Option<Vec<String>>) -> Self {
+            let anonymizer = NerAnonymizer::from_dir(&model_dir, entities.as_deref()).unwrap();
+
+            Ner {
+                anonymizer_instance: anonymizer,
+            }
+        }
+
+        pub fn anonymize_batch(
+            &self,
+            texts: Vec<&str>,
+            replacement: Option<&str>,
+        ) -> PyResult<Vec<(String, HashMap<String, String>)>> {
+            let results = self
+                .anonymizer_instance
+                .anonymize_batch(&texts, replacement)
+                .unwrap();
+            Ok(results.into_iter().map(|r| (r.text, r.items)).collect())
+        }
+
+        pub fn detect(&self, text: &str) -> PyResult<Vec<DetectedEntity>> {
+            let entities = self.anonymizer_instance.detect(text).unwrap();
+            Ok(entities
+                .into_iter()
+                .map(|e| (e.label, e.text, e.start, e.end, e.score.unwrap_or(1.0)))
+                .collect())
+        }
+
         pub fn anonymize(
             &self,
             text: &str,
@@ -74,14 +103,21 @@ fn anonymizerrs(py: Python, m: &PyModule) -> PyResult<()> {
     #[pymethods]
     impl Regex {
         #[new]
-        pub fn new(name: String, file: Option<String>, patterns: Option<Vec<String>>) -> Self {
+        pub fn new(
+            name: String,
+            file: Option<String>,
+            patterns: Option<Vec<String>>,
+            group: Option<usize>,
+        ) -> Self {
             let mut anonymizer = RegexAnonymizer::new(Some(name));
+            if let Some(g) = group {
+                anonymizer.set_group(g);
+            }
             if let Some(f) = file {
                 anonymizer.add_regex_patterns_file(&f).unwrap();
             };
             if let Some(p) = patterns {
-                p.iter()
-                    .for_each(|v| anonymizer.add_regex_pattern(v).unwrap());
+                anonymizer.add_regex_patterns(&p).unwrap();
             };
             Regex {
                 anonymizer_instance: anonymizer,
@@ -121,13 +157,25 @@ fn anonymizerrs(py: Python, m: &PyModule) -> PyResult<()> {
     #[pymethods]
     impl FlashText {
         #[new]
-        pub fn new(name: String, file: Option<String>, keywords: Option<Vec<String>>) -> Self {
-            let mut anonymizer = FlashTextAnonymizer::new(Some(name));
+        pub fn new(
+            name: String,
+            file: Option<String>,
+            keywords: Option<Vec<String>>,
+            ignore_case: Option<bool>,
+            nfkc: Option<bool>,
+            fold_diacritics: Option<bool>,
+        ) -> Self {
+            let normalizer = Normalizer::new(
+                ignore_case.unwrap_or_default(),
+                nfkc.unwrap_or_default(),
+                fold_diacritics.unwrap_or_default(),
+            );
+            let mut anonymizer = FlashTextAnonymizer::with_normalizer(Some(name), normalizer);
             if let Some(f) = file {
                 anonymizer.add_keywords_file(&f).unwrap();
             };
             if let Some(p) = keywords {
-                p.iter().for_each(|v| anonymizer.add_keyword(v).unwrap());
+                anonymizer.add_keywords(p).unwrap();
             };
             FlashText {
                 anonymizer_instance: anonymizer,
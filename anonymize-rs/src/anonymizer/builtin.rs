@@ -0,0 +1,143 @@
+use crate::anonymizer::regex_anonymizer::RegexAnonymizer;
+use crate::config::{Locale, ValidatorKind};
+use anyhow::{anyhow, Result};
+
+/// Curated regex recognizer, the entity label is the recognizer name.
+#[derive(Debug)]
+pub struct Recognizer {
+    pub name: &'static str,
+    pub patterns: &'static [&'static str],
+    pub validator: Option<ValidatorKind>,
+    /// Locales the recognizer is specific to, empty for recognizers used everywhere.
+    pub locales: &'static [Locale],
+}
+
+pub const RECOGNIZERS: &[Recognizer] = &[
+    Recognizer {
+        name: "EMAIL",
+        patterns: &[r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}\b"],
+        validator: None,
+        locales: &[],
+    },
+    Recognizer {
+        name: "URL",
+        patterns: &[r#"\b(?:https?://|www\.)[^\s<>"']*[^\s<>"'.,;:!?)\]]"#],
+        validator: None,
+        locales: &[],
+    },
+    Recognizer {
+        name: "IPV4",
+        patterns: &[
+            r"\b(?:25[0-5]|2[0-4]\d|1\d\d|[1-9]?\d)(?:\.(?:25[0-5]|2[0-4]\d|1\d\d|[1-9]?\d)){3}\b",
+        ],
+        validator: None,
+        locales: &[],
+    },
+    Recognizer {
+        name: "IPV6",
+        patterns: &[r"(?i)(?:\b[0-9a-f]{1,4})?(?::[0-9a-f]{0,4}){2,7}"],
+        validator: Some(ValidatorKind::Ipv6),
+        locales: &[],
+    },
+    Recognizer {
+        name: "IBAN",
+        patterns: &[r"\b[A-Z]{2}\d{2}(?: ?[A-Z0-9]{4}){2,7}(?: ?[A-Z0-9]{1,4})?\b"],
+        validator: Some(ValidatorKind::Iban),
+        locales: &[],
+    },
+    Recognizer {
+        name: "CREDIT_CARD",
+        patterns: &[r"\b(?:\d[ -]?){12,18}\d\b"],
+        validator: Some(ValidatorKind::Luhn),
+        locales: &[],
+    },
+    Recognizer {
+        name: "US_SSN",
+        patterns: &[r"\b\d{3}-\d{2}-\d{4}\b"],
+        validator: Some(ValidatorKind::Ssn),
+        locales: &[Locale::En],
+    },
+    Recognizer {
+        name: "US_PHONE",
+        patterns: &[r"(?:\+1[ -]?)?(?:\(\d{3}\) ?|\b\d{3}[-. ])\d{3}[-. ]\d{4}\b"],
+        validator: None,
+        locales: &[Locale::En],
+    },
+    Recognizer {
+        name: "PL_PESEL",
+        patterns: &[r"\b\d{11}\b"],
+        validator: Some(ValidatorKind::Pesel),
+        locales: &[Locale::Pl],
+    },
+    Recognizer {
+        name: "PL_NIP",
+        patterns: &[
+            r"\b\d{3}-?\d{3}-?\d{2}-?\d{2}\b",
+            r"\b\d{3}-\d{2}-\d{2}-\d{3}\b",
+        ],
+        validator: Some(ValidatorKind::Nip),
+        locales: &[Locale::Pl],
+    },
+    Recognizer {
+        name: "PL_REGON",
+        patterns: &[r"\b\d{9}\b|\b\d{14}\b"],
+        validator: Some(ValidatorKind::Regon),
+        locales: &[Locale::Pl],
+    },
+    Recognizer {
+        name: "PL_ID_CARD",
+        patterns: &[r"\b[A-Z]{3} ?\d{6}\b"],
+        validator: Some(ValidatorKind::IdCard),
+        locales: &[Locale::Pl],
+    },
+    Recognizer {
+        name: "PL_PASSPORT",
+        patterns: &[r"\b[A-Z]{2} ?\d{7}\b"],
+        validator: Some(ValidatorKind::Passport),
+        locales: &[Locale::Pl],
+    },
+    Recognizer {
+        name: "PL_PHONE",
+        patterns: &[r"\+48[ -]?\d{3}[ -]?\d{3}[ -]?\d{3}\b|\b\d{3}[ -]\d{3}[ -]\d{3}\b"],
+        validator: None,
+        locales: &[Locale::Pl],
+    },
+];
+
+impl Recognizer {
+    pub fn anonymizer(&self) -> Result<RegexAnonymizer> {
+        let mut anonymizer = RegexAnonymizer::new(Some(self.name.to_string()));
+        if let Some(validator) = self.validator {
+            anonymizer.set_validator(validator, None);
+        }
+        anonymizer.add_regex_patterns(self.patterns)?;
+        Ok(anonymizer)
+    }
+}
+
+pub fn recognizer(name: &str) -> Option<&'static Recognizer> {
+    RECOGNIZERS.iter().find(|r| r.name == name)
+}
+
+/// Recognizers selected by name (all when `names` is not set), in the given order.
+/// With `locales` only the recognizers used everywhere or in one of the locales are kept.
+pub fn recognizers(
+    names: Option<&[String]>,
+    locales: Option<&[Locale]>,
+) -> Result<Vec<&'static Recognizer>> {
+    let selected = match names {
+        Some(names) => names
+            .iter()
+            .map(|name| recognizer(name).ok_or(anyhow!("Unknown builtin recognizer {name}")))
+            .collect::<Result<Vec<_>>>()?,
+        None => RECOGNIZERS.iter().collect(),
+    };
+    Ok(selected
+        .into_iter()
+        .filter(|r| {
+            locales.map_or(true, |locales| {
+                r.locales.is_empty() || r.locales.iter().any(|l| locales.contains(l))
+            })
+        })
+        .collect())
+}
@@ -0,0 +1,79 @@
+use crate::anonymizer::Entity;
+use crate::config::ContextConfig;
+
+const DEFAULT_WINDOW: usize = 5;
+const DEFAULT_BOOST: f32 = 0.35;
+
+/// Scores matches by words found around them, e.g. `account` before a bare number.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Context {
+    words: Vec<String>,
+    window: usize,
+    boost: f32,
+    score: Option<f32>,
+}
+
+impl Context {
+    pub fn new(config: ContextConfig) -> Self {
+        Context {
+            words: config.words.iter().map(|w| w.to_lowercase()).collect(),
+            window: config.window.unwrap_or(DEFAULT_WINDOW),
+            boost: config.boost.unwrap_or(DEFAULT_BOOST),
+            score: config.score,
+        }
+    }
+
+    /// Entities with a context word among `window` words before or after them get
+    /// their score raised by `boost` (entities without score count as 1.0). Other
+    /// entities get at most `score`, or are dropped when it is not set. Words are
+    /// compared ignoring case and a text word matches when it starts with a context
+    /// word, so inflected forms are found too.
+    pub fn apply(&self, text: &str, entities: Vec<Entity>) -> Vec<Entity> {
+        if entities.is_empty() {
+            return entities;
+        }
+        let words = words(text);
+        entities
+            .into_iter()
+            .filter_map(|mut entity| {
+                let score = entity.score.unwrap_or(1.0);
+                if self.has_context(text, &words, &entity) {
+                    entity.score = Some((score + self.boost).min(1.0));
+                } else {
+                    entity.score = Some(score.min(self.score?));
+                }
+                Some(entity)
+            })
+            .collect()
+    }
+
+    /// Looks at the `window` words on each side of the entity, found by binary search
+    /// in the sorted word offsets.
+    fn has_context(&self, text: &str, words: &[(usize, usize)], entity: &Entity) -> bool {
+        let before_end = words.partition_point(|(_, end)| *end <= entity.start);
+        let after_start = words.partition_point(|(start, _)| *start < entity.end);
+        let before = &words[before_end.saturating_sub(self.window)..before_end];
+        let after = &words[after_start..(after_start + self.window).min(words.len())];
+        before.iter().chain(after).any(|(start, end)| {
+            let word = text[*start..*end].to_lowercase();
+            self.words.iter().any(|w| word.starts_with(w.as_str()))
+        })
+    }
+}
+
+/// Byte offsets of the words of the text, in order.
+fn words(text: &str) -> Vec<(usize, usize)> {
+    let mut words = vec![];
+    let mut start = None;
+    for (idx, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(idx),
+            (false, Some(s)) => {
+                words.push((s, idx));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    words
+}
@@ -0,0 +1,113 @@
+use crate::anonymizer::normalizer::Normalizer;
+use anyhow::{anyhow, Result};
+use memmap2::Mmap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::sync::Arc;
+
+const MAGIC: &[u8; 8] = b"ANONDICT";
+pub const DICTIONARY_VERSION: u32 = 2;
+// magic, version, normalizer flags, categories length, keywords length, crc32
+const HEADER_LEN: usize = 8 + 4 + 4 + 8 + 8 + 4;
+
+/// Bytes of a compiled keyword automaton, built in memory or mapped from a dictionary file.
+#[derive(Debug, Clone)]
+pub enum KeywordBytes {
+    Owned(Vec<u8>),
+    /// Dictionary file and the offset of the automaton in it.
+    Mapped(Arc<Mmap>, usize),
+}
+
+impl AsRef<[u8]> for KeywordBytes {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            KeywordBytes::Owned(bytes) => bytes,
+            KeywordBytes::Mapped(mmap, offset) => &mmap[*offset..],
+        }
+    }
+}
+
+/// Writes a dictionary file: a versioned header with the normalizer used for the
+/// keywords and a checksum, followed by keyword categories and the keyword automaton.
+/// An existing file is replaced only once the new one is complete, processes mapping
+/// it are not affected.
+pub fn write_dictionary(
+    path: &str,
+    normalizer: &Normalizer,
+    categories: &[String],
+    keywords: &[u8],
+) -> Result<()> {
+    let flags = normalizer.ignore_case as u32
+        | (normalizer.nfkc as u32) << 1
+        | (normalizer.fold_diacritics as u32) << 2;
+    let mut encoded_categories = Vec::new();
+    for category in categories {
+        encoded_categories.extend((category.len() as u32).to_le_bytes());
+        encoded_categories.extend(category.as_bytes());
+    }
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(&encoded_categories);
+    crc.update(keywords);
+
+    let tmp = format!("{path}.tmp");
+    let mut file = File::create(&tmp)?;
+    file.write_all(MAGIC)?;
+    file.write_all(&DICTIONARY_VERSION.to_le_bytes())?;
+    file.write_all(&flags.to_le_bytes())?;
+    file.write_all(&(encoded_categories.len() as u64).to_le_bytes())?;
+    file.write_all(&(keywords.len() as u64).to_le_bytes())?;
+    file.write_all(&crc.finalize().to_le_bytes())?;
+    file.write_all(&encoded_categories)?;
+    file.write_all(keywords)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Memory maps a dictionary file after checking its version and checksum.
+pub fn read_dictionary(path: &str) -> Result<(Normalizer, Vec<String>, KeywordBytes)> {
+    let file = File::open(path)?;
+    // dictionaries are never modified in place, `write_dictionary` replaces the file
+    let mmap = unsafe { Mmap::map(&file)? };
+    if mmap.len() < HEADER_LEN || &mmap[..8] != MAGIC {
+        return Err(anyhow!("{path} is not a keyword dictionary"));
+    }
+    let u32_at = |i: usize| u32::from_le_bytes(mmap[i..i + 4].try_into().unwrap());
+    let u64_at = |i: usize| u64::from_le_bytes(mmap[i..i + 8].try_into().unwrap());
+    let version = u32_at(8);
+    if version != DICTIONARY_VERSION {
+        return Err(anyhow!(
+            "{path} has dictionary version {version}, expected {DICTIONARY_VERSION}"
+        ));
+    }
+    let flags = u32_at(12);
+    let (categories_len, keywords_len) = (u64_at(16), u64_at(24));
+    if categories_len.checked_add(keywords_len) != Some((mmap.len() - HEADER_LEN) as u64)
+        || crc32fast::hash(&mmap[HEADER_LEN..]) != u32_at(32)
+    {
+        return Err(anyhow!("{path} is corrupted, checksum mismatch"));
+    }
+
+    // lengths are checked against the file, categories end within the mapped bytes
+    let categories_end = HEADER_LEN + categories_len as usize;
+    let invalid_category = || anyhow!("{path} is corrupted, invalid category length");
+    let mut categories = Vec::new();
+    let mut pos = HEADER_LEN;
+    while pos < categories_end {
+        if categories_end - pos < 4 {
+            return Err(invalid_category());
+        }
+        let end = (pos + 4)
+            .checked_add(u32_at(pos) as usize)
+            .filter(|end| *end <= categories_end)
+            .ok_or_else(invalid_category)?;
+        categories.push(String::from_utf8(mmap[pos + 4..end].to_vec())?);
+        pos = end;
+    }
+    let normalizer = Normalizer::new(flags & 1 != 0, flags & 2 != 0, flags & 4 != 0);
+    Ok((
+        normalizer,
+        categories,
+        KeywordBytes::Mapped(Arc::new(mmap), pos),
+    ))
+}
@@ -1,43 +1,176 @@
-use crate::anonymizer::Anonymizer;
-use crate::anonymizer::ReplaceResult;
-use crate::anonymizer::TrieNode;
+use crate::anonymizer::context::Context;
+use crate::anonymizer::dictionary::{read_dictionary, write_dictionary, KeywordBytes};
+use crate::anonymizer::normalizer::Normalizer;
+use crate::anonymizer::replacer::Replacer;
+use crate::anonymizer::{Anonymizer, Detector, Entity, ReplaceResult};
 use anyhow::{anyhow, Result};
-use std::collections::HashMap;
+use fst::raw::Output;
+use fst::{Map, MapBuilder, Streamer};
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
-use std::hash::Hash;
 use std::io::{self, BufRead};
-use tract_onnx::prelude::tract_itertools::Itertools;
+use unicode_normalization::char::is_combining_mark;
 
+/// Keyword matcher backed by a finite state transducer. Keywords are matched
+/// leftmost-longest at word starts and a match extends to the end of the word,
+/// so inflected forms (e.g. `apples` for `apple`) are found too. Keywords may have
+/// their own category used as the entity label instead of the stage name.
 #[derive(Debug, Clone)]
 pub struct FlashTextAnonymizer {
-    root: TrieNode,
+    /// Keyword to category index + 1, 0 for keywords without a category.
+    keywords: Map<KeywordBytes>,
+    categories: Vec<String>,
     replacement: Option<String>,
+    normalizer: Normalizer,
+    context: Option<Context>,
+}
+
+/// Keyword found in a text, offsets point into the original text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeywordMatch {
+    /// Matched keyword, normalized.
+    pub keyword: String,
+    pub category: Option<String>,
+    pub start: usize,
+    pub end: usize,
 }
 
 impl FlashTextAnonymizer {
     pub fn new(replacement: Option<String>) -> Self {
+        Self::with_normalizer(replacement, Normalizer::default())
+    }
+
+    /// Keywords and texts are matched after normalization, the original slice of the
+    /// text is what gets replaced.
+    pub fn with_normalizer(replacement: Option<String>, normalizer: Normalizer) -> Self {
         FlashTextAnonymizer {
-            root: TrieNode::new(),
+            keywords: empty_keywords(),
+            categories: Vec::new(),
             replacement,
+            normalizer,
+            context: None,
         }
     }
 
-    pub fn add_keywords_file(&mut self, path: &str) -> Result<()> {
-        let file = File::open(path)?;
-        io::BufReader::new(file)
-            .lines()
-            .try_for_each(|word| -> Result<()> { self.add_keyword(&word?) })?;
+    /// Loads keywords compiled with `compile` (memory mapped), the dictionary
+    /// normalization is used for the texts.
+    pub fn from_dictionary(replacement: Option<String>, path: &str) -> Result<Self> {
+        let (normalizer, categories, bytes) = read_dictionary(path)?;
+        Ok(FlashTextAnonymizer {
+            keywords: Map::new(bytes)?,
+            categories,
+            replacement,
+            normalizer,
+            context: None,
+        })
+    }
 
-        Ok(())
+    /// Writes the keywords to a dictionary file loaded by `from_dictionary`.
+    pub fn save_dictionary(&self, path: &str) -> Result<()> {
+        write_dictionary(
+            path,
+            &self.normalizer,
+            &self.categories,
+            self.keywords.as_fst().as_bytes(),
+        )
+    }
+
+    pub fn normalizer(&self) -> &Normalizer {
+        &self.normalizer
+    }
+
+    /// Scores matches by the words around them, see [`Context::apply`].
+    pub fn set_context(&mut self, context: Context) {
+        self.context = Some(context);
+    }
+
+    /// Reads a keyword per line, optionally followed by a tab and its category.
+    /// Files with the `.csv` extension are read as `keyword,category` records.
+    pub fn add_keywords_file(&mut self, path: &str) -> Result<()> {
+        let words = if path.ends_with(".csv") {
+            csv::ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_path(path)?
+                .records()
+                .map(|record| {
+                    let record = record?;
+                    let keyword = record.get(0).unwrap_or_default().to_string();
+                    let category = record.get(1).map(|c| c.trim().to_string());
+                    Ok((keyword, category))
+                })
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            let file = File::open(path)?;
+            io::BufReader::new(file)
+                .lines()
+                .map(|line| {
+                    let line = line?;
+                    Ok(match line.split_once('\t') {
+                        Some((keyword, category)) => {
+                            (keyword.to_string(), Some(category.trim().to_string()))
+                        }
+                        None => (line, None),
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?
+        };
+        self.add_categorized_keywords(words)
     }
 
     pub fn add_keyword(&mut self, word: &str) -> Result<()> {
-        let mut node = &mut self.root;
+        self.add_keywords([word])
+    }
 
-        for ch in word.chars() {
-            node = node.children.entry(ch).or_insert_with(TrieNode::new);
+    pub fn add_keyword_with_category(&mut self, word: &str, category: &str) -> Result<()> {
+        self.add_categorized_keywords([(word, Some(category))])
+    }
+
+    /// Adds keywords in bulk, the automaton is rebuilt once per call.
+    pub fn add_keywords<I, S>(&mut self, words: I) -> Result<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.add_categorized_keywords(words.into_iter().map(|w| (w, None::<&str>)))
+    }
+
+    /// Adds `(keyword, category)` pairs in bulk, a keyword added again gets the new category.
+    pub fn add_categorized_keywords<I, S, C>(&mut self, words: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (S, Option<C>)>,
+        S: AsRef<str>,
+        C: AsRef<str>,
+    {
+        let mut added = BTreeMap::new();
+        for (word, category) in words {
+            let word = self.normalizer.normalize(word.as_ref());
+            if word.is_empty() {
+                continue;
+            }
+            let value = match category.as_ref().map(|c| c.as_ref()) {
+                Some(c) if !c.is_empty() => match self.categories.iter().position(|x| x == c) {
+                    Some(i) => i as u64 + 1,
+                    None => {
+                        self.categories.push(c.to_string());
+                        self.categories.len() as u64
+                    }
+                },
+                _ => 0,
+            };
+            added.insert(word, value);
         }
-        node.is_word_end = true;
+        let added = Map::from_iter(added)?;
+
+        let mut builder = MapBuilder::memory();
+        {
+            let mut union = self.keywords.op().add(&added).union();
+            while let Some((keyword, values)) = union.next() {
+                let value = values.iter().max_by_key(|v| v.index).unwrap().value;
+                builder.insert(keyword, value)?;
+            }
+        }
+        self.keywords = Map::new(KeywordBytes::Owned(builder.into_inner()?))?;
         Ok(())
     }
 
@@ -47,123 +180,119 @@ impl FlashTextAnonymizer {
         replacement: Option<&str>,
         items: Option<HashMap<String, String>>,
     ) -> Result<ReplaceResult> {
-        let mut internal_text = text.to_string();
-        internal_text.push_str("  ");
-        let mut result = String::new();
-        let mut ch_indices = internal_text.char_indices();
-        let mut start = 0;
-
-        let mut items: HashMap<String, String> = match items {
-            Some(it) => it,
-            None => HashMap::new(),
-        };
-        let mut idx = 0;
-
-        let base_replacement = if replacement.is_some() {
-            replacement.ok_or(anyhow!("SET REPLACEMENT"))?.to_string()
-        } else {
-            self.replacement.clone().ok_or(anyhow!("SET REPLACEMENT"))?
-        };
-
-        while let Some((match_start, ch)) = ch_indices.next() {
-            if let Some(_word) = self.traverse_trie(ch, &mut ch_indices) {
-                result.push_str(&internal_text[start..match_start]);
-                let mut rep = base_replacement.to_string();
-                rep.push_str(&idx.to_string());
-                start = self.skip_to_word_boundary(
-                    &internal_text,
-                    match_start + ch.len_utf8(),
-                    &mut ch_indices,
-                );
-                let (item_value, addition) =
-                    self.process_item_value(&internal_text[match_start..start]);
-                let existing_item = items.iter().find(|(_, v)| *v == &item_value);
-                match existing_item {
-                    Some((k, _v)) => {
-                        rep = k.to_string();
-                    }
-                    None => {
-                        items.insert(rep.to_string(), item_value);
-                        idx += 1;
-                    }
-                }
-
-                result.push_str(&rep);
-                result.push_str(&addition);
-            }
-        }
-        result.push_str(&internal_text[start..]);
-        result = result.trim_end().to_string();
+        let entities = self.detect_keywords(text, replacement)?;
+        Replacer::default().replace(text, &entities, None, items)
+    }
 
-        Ok(ReplaceResult {
-            text: result,
-            items,
+    /// Keywords labeled with the `replacement` when given, otherwise with their
+    /// category or the stage name.
+    fn detect_keywords(&self, text: &str, replacement: Option<&str>) -> Result<Vec<Entity>> {
+        let entities = self
+            .find_keywords(text)
+            .into_iter()
+            .filter(|m| m.start < m.end)
+            .map(|m| {
+                let label = replacement
+                    .or(m.category.as_deref())
+                    .or(self.replacement.as_deref())
+                    .ok_or(anyhow!("SET REPLACEMENT"))?;
+                Ok(Entity::new(text, label, m.start, m.end, "flashText", None))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(match &self.context {
+            Some(context) => context.apply(text, entities),
+            None => entities,
         })
     }
 
-    fn process_item_value(&self, item_value: &str) -> (String, String) {
-        let final_value: String = item_value
-            .trim_end_matches(|c: char| !c.is_alphabetic())
-            .to_string();
-        let addition = item_value[final_value.len()..].to_string();
-        (final_value, addition)
+    /// Keywords found in the text, in the order of occurrence. A match spans the whole
+    /// word the keyword starts.
+    pub fn find_keywords(&self, text: &str) -> Vec<KeywordMatch> {
+        let (normalized, offsets) = self.normalizer.normalize_with_offsets(text);
+        self.find_matches(&normalized)
+            .into_iter()
+            .map(|(start, keyword_end, end, value)| KeywordMatch {
+                keyword: normalized[start..keyword_end].to_string(),
+                category: value
+                    .checked_sub(1)
+                    .map(|i| self.categories[i as usize].to_string()),
+                start: offsets[start],
+                end: offsets[end],
+            })
+            .collect()
     }
 
-    pub fn find_keywords(&self, text: &str) -> Vec<String> {
-        let mut result = Vec::new();
-        let mut ch_indices = text.char_indices();
-        while let Some((_start, ch)) = ch_indices.next() {
-            if let Some(word) = self.traverse_trie(ch, &mut ch_indices) {
-                result.push(word);
+    /// `(start, keyword end, word end, value)` of non-overlapping matches.
+    fn find_matches(&self, text: &str) -> Vec<(usize, usize, usize, u64)> {
+        let fst = self.keywords.as_fst();
+        let bytes = text.as_bytes();
+        let mut matches = Vec::new();
+        let mut start = 0;
+        while start < text.len() {
+            if !is_word_start(text, start) {
+                start += next_char_len(text, start);
+                continue;
             }
-        }
-        result
-    }
 
-    fn traverse_trie<I>(&self, ch: char, ch_indices: &mut I) -> Option<String>
-    where
-        I: Iterator<Item = (usize, char)>,
-    {
-        let mut node = self.root.children.get(&ch)?;
-        let mut chars = vec![ch];
-
-        for (_next_start, next_ch) in ch_indices.by_ref() {
-            if let Some(next_node) = node.children.get(&next_ch) {
-                chars.push(next_ch);
-                node = next_node;
-            } else {
-                break;
+            let mut node = fst.root();
+            let mut output = Output::zero();
+            let mut keyword_end = None;
+            for (i, b) in bytes[start..].iter().enumerate() {
+                match node.find_input(*b) {
+                    Some(t) => {
+                        let transition = node.transition(t);
+                        output = output.cat(transition.out);
+                        node = fst.node(transition.addr);
+                    }
+                    None => break,
+                }
+                if node.is_final() {
+                    keyword_end = Some((start + i + 1, output.cat(node.final_output()).value()));
+                }
             }
-        }
 
-        if node.is_word_end {
-            Some(chars.into_iter().collect())
-        } else {
-            None
-        }
-    }
-
-    fn skip_to_word_boundary<I>(&self, text: &str, start: usize, ch_indices: &mut I) -> usize
-    where
-        I: Iterator<Item = (usize, char)>,
-    {
-        let mut end = start;
-        for (next_start, _) in ch_indices.by_ref() {
-            if self.is_word_boundary(text, next_start) {
-                end = next_start;
-                break;
+            match keyword_end {
+                Some((keyword_end, value)) => {
+                    let end = word_end(text, keyword_end);
+                    matches.push((start, keyword_end, end, value));
+                    start = end;
+                }
+                None => start += next_char_len(text, start),
             }
         }
-        end
+        matches
     }
+}
 
-    fn is_word_boundary(&self, text: &str, index: usize) -> bool {
-        if index >= text.len() {
-            return true;
-        }
+fn empty_keywords() -> Map<KeywordBytes> {
+    let bytes = MapBuilder::memory()
+        .into_inner()
+        .expect("empty keyword map");
+    Map::new(KeywordBytes::Owned(bytes)).expect("empty keyword map")
+}
+
+fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || is_combining_mark(ch)
+}
+
+fn is_word_start(text: &str, index: usize) -> bool {
+    !text[..index].chars().next_back().is_some_and(is_word_char)
+}
+
+fn word_end(text: &str, index: usize) -> usize {
+    text[index..]
+        .char_indices()
+        .find(|(_, ch)| !is_word_char(*ch))
+        .map_or(text.len(), |(i, _)| index + i)
+}
+
+fn next_char_len(text: &str, index: usize) -> usize {
+    text[index..].chars().next().map_or(1, char::len_utf8)
+}
 
-        let ch = text.chars().nth(index).unwrap();
-        !ch.is_alphabetic()
+impl Detector for FlashTextAnonymizer {
+    fn detect(&self, text: &str) -> Result<Vec<Entity>> {
+        self.detect_keywords(text, None)
     }
 }
 
@@ -1,14 +1,30 @@
+use crate::anonymizer::context::Context;
 use crate::anonymizer::flashtext_anonymizer::FlashTextAnonymizer;
 use crate::anonymizer::ner_anonymizer::NerAnonymizer;
+use crate::anonymizer::normalizer::Normalizer;
+use crate::anonymizer::operator::Operator;
+use crate::anonymizer::placeholder::Placeholder;
 use crate::anonymizer::regex_anonymizer::RegexAnonymizer;
-use crate::config::{AnonymizePipelineConfig, AnonymizerConfig};
-use anyhow::Result;
+use crate::anonymizer::replacer::Replacer;
+use crate::anonymizer::vault::Vault;
+use crate::config::{AnonymizePipelineConfig, AnonymizerConfig, OverlapStrategy, RegexEngine};
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::hash::Hash;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+pub mod builtin;
+pub mod context;
+pub mod dictionary;
 pub mod flashtext_anonymizer;
 pub mod ner_anonymizer;
+pub mod normalizer;
+pub mod operator;
+pub mod placeholder;
 pub mod regex_anonymizer;
+pub mod replacer;
+pub mod surrogate;
+pub mod validator;
+pub mod vault;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ReplaceResult {
@@ -16,20 +32,86 @@ pub struct ReplaceResult {
     pub items: HashMap<String, String>,
 }
 
-pub trait Anonymizer: AnonymizerClone {
+/// Span found by a detector. Offsets always point into the original text.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entity {
+    pub label: String,
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+    pub char_start: usize,
+    pub char_end: usize,
+    pub source: String,
+    pub score: Option<f32>,
+    /// Index of the pipeline stage which detected the entity.
+    #[serde(skip)]
+    pub stage: Option<usize>,
+}
+
+impl Entity {
+    pub fn new(
+        text: &str,
+        label: &str,
+        start: usize,
+        end: usize,
+        source: &str,
+        score: Option<f32>,
+    ) -> Self {
+        let char_start = text[..start].chars().count();
+        let char_end = char_start + text[start..end].chars().count();
+        Entity {
+            label: label.to_string(),
+            text: text[start..end].to_string(),
+            start,
+            end,
+            char_start,
+            char_end,
+            source: source.to_string(),
+            score,
+            stage: None,
+        }
+    }
+
+    pub fn overlaps(&self, other: &Entity) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+}
+
+pub trait Detector {
+    fn detect(&self, text: &str) -> Result<Vec<Entity>>;
+
+    /// Detects entities in many texts, detectors running a model override it to
+    /// process the texts together.
+    fn detect_batch(&self, texts: &[&str]) -> Result<Vec<Vec<Entity>>> {
+        texts.iter().map(|text| self.detect(text)).collect()
+    }
+}
+
+pub trait Anonymizer: Detector + AnonymizerClone + Send + Sync {
     fn anonymize(
         &self,
         text: &str,
         replacement: Option<&str>,
         items: Option<HashMap<String, String>>,
-    ) -> Result<ReplaceResult>;
+    ) -> Result<ReplaceResult> {
+        let entities = self.detect(text)?;
+        Replacer::default().replace(text, &entities, replacement, items)
+    }
+
+    /// Anonymizes every text separately, each result has its own `items`.
+    fn anonymize_batch(
+        &self,
+        texts: &[&str],
+        replacement: Option<&str>,
+    ) -> Result<Vec<ReplaceResult>> {
+        texts
+            .iter()
+            .map(|text| self.anonymize(text, replacement, None))
+            .collect()
+    }
 
     fn deanonymize(&self, input: ReplaceResult) -> String {
-        let mut result = input.text;
-        for (keyword, replacement) in input.items {
-            result = result.replace(&keyword, &replacement);
-        }
-        result
+        Replacer::default().deanonymize(input)
     }
 }
 
@@ -55,10 +137,30 @@ impl Clone for Box<dyn Anonymizer> {
 #[derive(Clone)]
 pub struct AnonymizePipeline {
     pub anonymizers: Vec<Box<dyn Anonymizer>>,
+    pub overlap: OverlapStrategy,
+    pub min_score: Option<f32>,
+    pub replacer: Replacer,
 }
 
 impl AnonymizePipeline {
     pub fn new(anonymize_config: AnonymizePipelineConfig) -> Result<Self> {
+        let placeholder = match &anonymize_config.placeholder {
+            Some(template) => Placeholder::new(template)?,
+            None => Placeholder::default(),
+        };
+        let vault = match &anonymize_config.vault {
+            Some(v) => {
+                let mut vault = Vault::open(&v.path, v.namespace.as_deref())?;
+                vault.set_sync(v.sync.unwrap_or_default());
+                Some(vault)
+            }
+            None => None,
+        };
+        let mut operators = HashMap::new();
+        for (label, operator) in anonymize_config.operators.unwrap_or_default() {
+            operators.insert(label, Operator::new(operator)?);
+        }
+        let mut stage_operators = HashMap::new();
         let mut anonymizers: Vec<Box<dyn Anonymizer>> = vec![];
         for c in anonymize_config.pipeline {
             match c {
@@ -66,14 +168,37 @@ impl AnonymizePipeline {
                     name,
                     file,
                     keywords,
+                    dictionary,
+                    ignore_case,
+                    nfkc,
+                    fold_diacritics,
+                    context,
                 } => {
-                    let mut anonymizer = FlashTextAnonymizer::new(Some(name));
+                    let normalizer = Normalizer::new(
+                        ignore_case.unwrap_or_default(),
+                        nfkc.unwrap_or_default(),
+                        fold_diacritics.unwrap_or_default(),
+                    );
+                    let mut anonymizer = match dictionary {
+                        Some(d) => {
+                            let anonymizer = FlashTextAnonymizer::from_dictionary(Some(name), &d)?;
+                            if !normalizer.is_identity() && *anonymizer.normalizer() != normalizer {
+                                return Err(anyhow!(
+                                    "Normalization of {d} differs from the stage configuration"
+                                ));
+                            }
+                            anonymizer
+                        }
+                        None => FlashTextAnonymizer::with_normalizer(Some(name), normalizer),
+                    };
+                    if let Some(c) = context {
+                        anonymizer.set_context(Context::new(c));
+                    }
                     if let Some(f) = file {
                         anonymizer.add_keywords_file(&f)?;
                     };
                     if let Some(k) = keywords {
-                        k.iter()
-                            .try_for_each(|v| -> Result<()> { anonymizer.add_keyword(v) })?;
+                        anonymizer.add_keywords(k)?;
                     };
                     anonymizers.push(Box::new(anonymizer));
                 }
@@ -81,34 +206,201 @@ impl AnonymizePipeline {
                     name,
                     file,
                     patterns,
+                    group,
+                    overlap,
+                    validator,
+                    invalid_score,
+                    context,
+                    engine,
+                    backtrack_limit,
+                    time_limit_ms,
+                    operator,
                 } => {
+                    if let Some(o) = operator {
+                        stage_operators.insert(anonymizers.len(), Operator::new(o)?);
+                    }
+                    let limited = backtrack_limit.is_some() || time_limit_ms.is_some();
+                    if limited && engine != Some(RegexEngine::Fancy) {
+                        return Err(anyhow!(
+                            "backtrack_limit and time_limit_ms of {name} require engine: fancy"
+                        ));
+                    }
                     let mut anonymizer = RegexAnonymizer::new(Some(name));
+                    if let Some(e) = engine {
+                        anonymizer.set_engine(
+                            e,
+                            backtrack_limit,
+                            time_limit_ms.map(Duration::from_millis),
+                        );
+                    }
+                    if let Some(g) = group {
+                        anonymizer.set_group(g);
+                    }
+                    if let Some(o) = overlap {
+                        anonymizer.set_overlap(o);
+                    }
+                    if let Some(v) = validator {
+                        anonymizer.set_validator(v, invalid_score);
+                    }
+                    if let Some(c) = context {
+                        anonymizer.set_context(Context::new(c));
+                    }
                     if let Some(f) = file {
                         anonymizer.add_regex_patterns_file(&f)?;
                     };
                     if let Some(p) = patterns {
-                        p.iter()
-                            .try_for_each(|v| -> Result<()> { anonymizer.add_regex_pattern(v) })?;
+                        anonymizer.add_regex_patterns(&p)?;
                     };
                     anonymizers.push(Box::new(anonymizer));
                 }
+                AnonymizerConfig::Builtin {
+                    recognizers,
+                    locales,
+                    operator,
+                } => {
+                    for recognizer in
+                        builtin::recognizers(recognizers.as_deref(), locales.as_deref())?
+                    {
+                        if let Some(o) = &operator {
+                            stage_operators.insert(anonymizers.len(), Operator::new(o.clone())?);
+                        }
+                        anonymizers.push(Box::new(recognizer.anonymizer()?));
+                    }
+                }
                 AnonymizerConfig::Ner {
                     model_path,
                     tokenizer_path,
                     id2label,
                     token_type_ids_included,
+                    model_dir,
+                    entities,
+                    aggregation_strategy,
+                    min_score,
+                    thresholds,
+                    rename,
+                    max_length,
+                    stride,
+                    batch_size,
                 } => {
-                    let anonymizer = NerAnonymizer::new(
-                        model_path,
-                        tokenizer_path,
-                        id2label,
-                        token_type_ids_included,
-                    )?;
+                    let explicit = model_path.is_some()
+                        || tokenizer_path.is_some()
+                        || id2label.is_some()
+                        || token_type_ids_included.is_some();
+                    if model_dir.is_some() && explicit {
+                        return Err(anyhow!(
+                            "NER model_dir can't be combined with model_path, tokenizer_path, id2label or token_type_ids_included"
+                        ));
+                    }
+                    let mut anonymizer = match (model_dir, model_path, tokenizer_path, id2label) {
+                        (Some(dir), _, _, _) => NerAnonymizer::from_dir(&dir, entities.as_deref())?,
+                        (None, Some(model), Some(tokenizer), Some(labels)) => {
+                            NerAnonymizer::new(model, tokenizer, labels, token_type_ids_included)?
+                        }
+                        _ => {
+                            return Err(anyhow!(
+                                "NER requires model_dir or model_path, tokenizer_path and id2label"
+                            ))
+                        }
+                    };
+                    if let Some(strategy) = aggregation_strategy {
+                        anonymizer.set_aggregation_strategy(strategy);
+                    }
+                    anonymizer.set_thresholds(min_score, thresholds.unwrap_or_default());
+                    anonymizer.set_rename(rename.unwrap_or_default());
+                    if let Some(size) = batch_size {
+                        anonymizer.set_batch_size(size);
+                    }
+                    if let Some(length) = max_length {
+                        anonymizer.set_window(length, stride)?;
+                    }
                     anonymizers.push(Box::new(anonymizer));
                 }
             };
         }
-        Ok(AnonymizePipeline { anonymizers })
+        let mut replacer = Replacer::new(placeholder, operators, vault);
+        for (stage, operator) in stage_operators {
+            replacer.set_stage_operator(stage, operator);
+        }
+        Ok(AnonymizePipeline {
+            anonymizers,
+            overlap: anonymize_config.overlap.unwrap_or_default(),
+            min_score: anonymize_config.min_score,
+            replacer,
+        })
+    }
+}
+
+/// Picks non-overlapping entities from candidates produced by several stages.
+/// Candidates are `(stage_index, entity)` pairs, a lower stage index has higher priority.
+pub fn resolve_overlaps(
+    mut candidates: Vec<(usize, Entity)>,
+    strategy: &OverlapStrategy,
+) -> Vec<Entity> {
+    let len = |e: &Entity| e.end - e.start;
+    let score = |e: &Entity| e.score.unwrap_or(1.0);
+    candidates.sort_by(|(ia, a), (ib, b)| {
+        let by_priority = ia.cmp(ib).then(a.start.cmp(&b.start));
+        let by_length = len(b).cmp(&len(a));
+        match strategy {
+            OverlapStrategy::Priority => by_priority,
+            OverlapStrategy::Longest => by_length.then(by_priority),
+            OverlapStrategy::Score => score(b)
+                .total_cmp(&score(a))
+                .then(by_length)
+                .then(by_priority),
+        }
+    });
+
+    // accepted entities keyed by `(start, end)`, disjoint so only the ones just
+    // before the candidate end can overlap it
+    let mut resolved: BTreeMap<(usize, usize), Entity> = BTreeMap::new();
+    for (_, candidate) in candidates {
+        let overlaps = resolved
+            .range(..(candidate.end, 0))
+            .rev()
+            .take_while(|(_, e)| e.end > candidate.start)
+            .any(|(_, e)| e.overlaps(&candidate));
+        if !overlaps {
+            resolved.insert((candidate.start, candidate.end), candidate);
+        }
+    }
+    resolved.into_values().collect()
+}
+
+impl AnonymizePipeline {
+    /// Drops candidates below `min_score` and resolves overlaps of the rest.
+    fn resolve(&self, mut candidates: Vec<(usize, Entity)>) -> Vec<Entity> {
+        if let Some(min_score) = self.min_score {
+            candidates.retain(|(_, e)| e.score.unwrap_or(1.0) >= min_score);
+        }
+        resolve_overlaps(candidates, &self.overlap)
+    }
+}
+
+fn staged(stage: usize, mut entity: Entity) -> (usize, Entity) {
+    entity.stage = Some(stage);
+    (stage, entity)
+}
+
+impl Detector for AnonymizePipeline {
+    fn detect(&self, text: &str) -> Result<Vec<Entity>> {
+        let mut candidates = vec![];
+        for (idx, anonymizer) in self.anonymizers.iter().enumerate() {
+            candidates.extend(anonymizer.detect(text)?.into_iter().map(|e| staged(idx, e)));
+        }
+        Ok(self.resolve(candidates))
+    }
+
+    fn detect_batch(&self, texts: &[&str]) -> Result<Vec<Vec<Entity>>> {
+        let mut candidates = vec![vec![]; texts.len()];
+        for (idx, anonymizer) in self.anonymizers.iter().enumerate() {
+            for (text_candidates, entities) in
+                candidates.iter_mut().zip(anonymizer.detect_batch(texts)?)
+            {
+                text_candidates.extend(entities.into_iter().map(|e| staged(idx, e)));
+            }
+        }
+        Ok(candidates.into_iter().map(|c| self.resolve(c)).collect())
     }
 }
 
@@ -119,41 +411,23 @@ impl Anonymizer for AnonymizePipeline {
         replacement: Option<&str>,
         items: Option<HashMap<String, String>>,
     ) -> Result<ReplaceResult> {
-        let mut replace_result = ReplaceResult {
-            text: text.to_string(),
-            items: HashMap::new(),
-        };
+        let entities = self.detect(text)?;
+        self.replacer.replace(text, &entities, replacement, items)
+    }
 
-        let mut result_items = HashMap::new();
-        self.anonymizers
+    fn anonymize_batch(
+        &self,
+        texts: &[&str],
+        replacement: Option<&str>,
+    ) -> Result<Vec<ReplaceResult>> {
+        texts
             .iter()
-            .try_for_each(|anonymizer| -> Result<()> {
-                let result = anonymizer.anonymize(
-                    &replace_result.text,
-                    replacement,
-                    Some(result_items.clone()),
-                )?;
-                replace_result.text = result.text;
-                result_items = result.items;
-                Ok(())
-            })?;
-
-        replace_result.items = result_items;
-        Ok(replace_result)
+            .zip(self.detect_batch(texts)?)
+            .map(|(text, entities)| self.replacer.replace(text, &entities, replacement, None))
+            .collect()
     }
-}
 
-#[derive(Default, Debug, Clone)]
-pub struct TrieNode {
-    children: HashMap<char, TrieNode>,
-    is_word_end: bool,
-}
-
-impl TrieNode {
-    fn new() -> Self {
-        TrieNode {
-            children: HashMap::new(),
-            is_word_end: false,
-        }
+    fn deanonymize(&self, input: ReplaceResult) -> String {
+        self.replacer.deanonymize(input)
     }
 }
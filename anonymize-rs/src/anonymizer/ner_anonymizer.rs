@@ -1,12 +1,19 @@
-use crate::anonymizer::{Anonymizer, ReplaceResult};
+use crate::anonymizer::replacer::Replacer;
+use crate::anonymizer::{Anonymizer, Detector, Entity, ReplaceResult};
+use crate::config::AggregationStrategy;
 use anyhow::{anyhow, Result};
-use std::collections::HashMap;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
 use std::path::Path;
 use std::time::Instant;
-use tokenizers::tokenizer::Tokenizer;
+use tokenizers::tokenizer::{Encoding, Tokenizer};
+use tokenizers::utils::truncation::TruncationParams;
 use tract_ndarray::Axis;
 use tract_onnx::prelude::*;
 
+const DEFAULT_BATCH_SIZE: usize = 16;
+
 type NerModel = SimplePlan<TypedFact, Box<dyn TypedOp>, Graph<TypedFact, Box<dyn TypedOp>>>;
 
 #[derive(Debug, Clone)]
@@ -14,7 +21,22 @@ pub struct NerAnonymizer {
     model: NerModel,
     tokenizer: Tokenizer,
     id2label: HashMap<String, (String, bool)>,
-    token_type_ids_included: Option<bool>,
+    input_names: Vec<String>,
+    aggregation_strategy: AggregationStrategy,
+    min_score: Option<f32>,
+    thresholds: HashMap<String, f32>,
+    rename: HashMap<String, String>,
+    batch_size: usize,
+}
+
+/// Model output for a single token.
+#[derive(Debug, Clone)]
+pub struct TokenPrediction {
+    pub start: usize,
+    pub end: usize,
+    pub word_id: Option<u32>,
+    /// Softmax scores indexed by label id.
+    pub scores: Vec<f32>,
 }
 
 impl NerAnonymizer {
@@ -23,17 +45,49 @@ impl NerAnonymizer {
         tokenizer_path: String,
         id2label: HashMap<String, (String, bool)>,
         token_type_ids_included: Option<bool>,
+    ) -> Result<Self> {
+        let mut input_names = vec!["input_ids".to_string(), "attention_mask".to_string()];
+        if let Some(true) = token_type_ids_included {
+            input_names.push("token_type_ids".to_string());
+        }
+        let model = tract_onnx::onnx().model_for_path(Path::new(&model_path))?;
+        Self::load(model, &tokenizer_path, id2label, input_names)
+    }
+
+    /// Loads `model.onnx`, `tokenizer.json` and the label map from `config.json` of a
+    /// Hugging Face model directory, model inputs are taken from the ONNX graph.
+    /// Only labels of the given entity types are anonymized, all of them without `entities`.
+    pub fn from_dir(model_dir: &str, entities: Option<&[String]>) -> Result<Self> {
+        let dir = Path::new(model_dir);
+        let id2label = id2label_from_config(&dir.join("config.json"), entities)?;
+        let model = tract_onnx::onnx().model_for_path(dir.join("model.onnx"))?;
+        let input_names = model
+            .input_outlets()?
+            .iter()
+            .map(|outlet| model.node(outlet.node).name.clone())
+            .collect();
+        let tokenizer_path = dir.join("tokenizer.json");
+        Self::load(
+            model,
+            &tokenizer_path.to_string_lossy(),
+            id2label,
+            input_names,
+        )
+    }
+
+    fn load(
+        model: InferenceModel,
+        tokenizer_path: &str,
+        id2label: HashMap<String, (String, bool)>,
+        input_names: Vec<String>,
     ) -> Result<Self> {
         let now = Instant::now();
-        let model = tract_onnx::onnx()
-            .model_for_path(Path::new(&model_path))?
-            .into_optimized()?
-            .into_runnable()?;
+        let model = model.into_optimized()?.into_runnable()?;
         let elapsed = now.elapsed();
         println!("MODEL LOADED: {:.2?}", elapsed);
 
         let now = Instant::now();
-        let tokenizer = Tokenizer::from_file(Path::new(&tokenizer_path)).unwrap();
+        let tokenizer = Tokenizer::from_file(Path::new(tokenizer_path)).unwrap();
 
         let elapsed = now.elapsed();
         println!("TOKENIZER LOADED: {:.2?}", elapsed);
@@ -42,145 +96,357 @@ impl NerAnonymizer {
             model,
             tokenizer,
             id2label,
-            token_type_ids_included,
+            input_names,
+            aggregation_strategy: AggregationStrategy::default(),
+            min_score: None,
+            thresholds: HashMap::new(),
+            rename: HashMap::new(),
+            batch_size: DEFAULT_BATCH_SIZE,
         })
     }
 
+    pub fn set_aggregation_strategy(&mut self, strategy: AggregationStrategy) {
+        self.aggregation_strategy = strategy;
+    }
+
+    /// Splits texts longer than `max_length` tokens (special tokens included) into windows
+    /// overlapping by `stride` tokens.
+    pub fn set_window(&mut self, max_length: usize, stride: Option<usize>) -> Result<()> {
+        self.tokenizer
+            .with_truncation(Some(TruncationParams {
+                max_length,
+                stride: stride.unwrap_or_default(),
+                ..Default::default()
+            }))
+            .map_err(|e| anyhow!(e))?;
+        Ok(())
+    }
+
+    /// Maximum number of windows run through the model at once.
+    pub fn set_batch_size(&mut self, batch_size: usize) {
+        self.batch_size = batch_size;
+    }
+
+    /// Output names for model labels, a label is looked up as is and then by its
+    /// entity type (without `B-`/`I-`). Labels renamed to the same name share placeholders.
+    pub fn set_rename(&mut self, rename: HashMap<String, String>) {
+        self.rename = rename;
+    }
+
+    /// Entities scored below the threshold of their label, or `min_score` for labels
+    /// without one, are dropped.
+    pub fn set_thresholds(&mut self, min_score: Option<f32>, thresholds: HashMap<String, f32>) {
+        self.min_score = min_score;
+        self.thresholds = thresholds;
+    }
+
     pub fn replace_matches(
         &self,
         text: &str,
         replacement: Option<&str>,
         items: Option<HashMap<String, String>>,
     ) -> Result<ReplaceResult> {
-        if let Some(_rep) = replacement {
-            todo!("Functionality not implemented");
+        let entities = self.detect_entities(text)?;
+        Replacer::default().replace(text, &entities, replacement, items)
+    }
+
+    fn detect_entities(&self, text: &str) -> Result<Vec<Entity>> {
+        Ok(self.detect_entities_batch(&[text])?.pop().unwrap())
+    }
+
+    /// Runs windows of all texts through the model in batches of `batch_size`.
+    fn detect_entities_batch(&self, texts: &[&str]) -> Result<Vec<Vec<Entity>>> {
+        let encodings = texts
+            .iter()
+            .map(|text| self.tokenizer.encode(*text, true).unwrap())
+            .collect::<Vec<_>>();
+        // (text index, window) pairs
+        let windows = encodings
+            .iter()
+            .enumerate()
+            .flat_map(|(i, encoding)| {
+                std::iter::once((i, encoding))
+                    .chain(encoding.get_overflowing().iter().map(move |o| (i, o)))
+            })
+            .collect::<Vec<_>>();
+
+        let mut text_windows: Vec<Vec<Vec<TokenPrediction>>> = vec![Vec::new(); texts.len()];
+        for chunk in windows.chunks(self.batch_size.max(1)) {
+            let batch = chunk.iter().map(|(_, w)| *w).collect::<Vec<_>>();
+            for ((i, _), predictions) in chunk.iter().zip(self.predict_windows(&batch)?) {
+                text_windows[*i].push(predictions);
+            }
         }
 
-        let tokenizer_output = self.tokenizer.encode(text, true).unwrap();
-        let input_ids = tokenizer_output.get_ids();
-        let attention_mask = tokenizer_output.get_attention_mask();
-        let length = input_ids.len();
-        let offsets = tokenizer_output.get_offsets();
-
-        let input_ids: Tensor = tract_ndarray::Array2::from_shape_vec(
-            (1, length),
-            input_ids.iter().map(|&x| x as i64).collect(),
-        )?
-        .into();
-        let attention_mask: Tensor = tract_ndarray::Array2::from_shape_vec(
-            (1, length),
-            attention_mask.iter().map(|&x| x as i64).collect(),
-        )?
-        .into();
-
-        let outputs = if let Some(true) = self.token_type_ids_included {
-            let token_type_ids = tokenizer_output.get_type_ids();
-            let token_type_ids: Tensor = tract_ndarray::Array2::from_shape_vec(
-                (1, length),
-                token_type_ids.iter().map(|&x| x as i64).collect(),
-            )?
-            .into();
-
-            self.model.run(tvec!(
-                input_ids.into(),
-                attention_mask.into(),
-                token_type_ids.into()
-            ))?
-        } else {
-            self.model
-                .run(tvec!(input_ids.into(), attention_mask.into(),))?
-        };
+        Ok(texts
+            .iter()
+            .zip(text_windows)
+            .map(|(text, windows)| {
+                let entities = aggregate_predictions(
+                    text,
+                    &merge_windows(windows),
+                    &self.id2label,
+                    &self.aggregation_strategy,
+                );
+                let mut entities = filter_scores(entities, self.min_score, &self.thresholds);
+                rename_labels(&mut entities, &self.rename);
+                entities
+            })
+            .collect())
+    }
 
-        let id2label = self.id2label.clone();
+    /// Pads the windows into one `[batch, seq]` input and splits the output per window.
+    fn predict_windows(&self, windows: &[&Encoding]) -> Result<Vec<Vec<TokenPrediction>>> {
+        let batch = windows.len();
+        let length = windows.iter().map(|w| w.len()).max().unwrap_or_default();
+        let pad_id = self
+            .tokenizer
+            .get_padding()
+            .map(|p| p.pad_id as i64)
+            .unwrap_or_default();
+        let tensor = |values: fn(&Encoding) -> &[u32], pad: i64| -> Result<Tensor> {
+            let mut array = tract_ndarray::Array2::from_elem((batch, length), pad);
+            for (i, window) in windows.iter().enumerate() {
+                for (j, v) in values(window).iter().enumerate() {
+                    array[[i, j]] = *v as i64;
+                }
+            }
+            Ok(array.into())
+        };
+        let inputs = self
+            .input_names
+            .iter()
+            .map(|name| match name.as_str() {
+                "input_ids" => tensor(|w| w.get_ids(), pad_id),
+                "attention_mask" => tensor(|w| w.get_attention_mask(), 0),
+                "token_type_ids" => tensor(|w| w.get_type_ids(), 0),
+                _ => Err(anyhow!("Unsupported model input: {name}")),
+            })
+            .map(|t| t.map(|t| t.into()))
+            .collect::<Result<TVec<TValue>>>()?;
+        let outputs = self.model.run(inputs)?;
 
-        let mut replacements = Vec::new();
-        outputs[0]
-            .to_array_view::<f32>()?
-            .axis_iter(Axis(0))
-            .last()
-            .unwrap()
+        let logits = outputs[0].to_array_view::<f32>()?;
+        Ok(logits
             .axis_iter(Axis(0))
-            .enumerate()
-            .for_each(|(i, x)| {
-                let result_exp = x.mapv(f32::exp);
-                let results_exp_sum = result_exp.sum();
-                let softmax = result_exp.mapv(|v| v / results_exp_sum);
-                let label_indices = softmax
-                    .iter()
+            .zip(windows)
+            .map(|(rows, window)| {
+                let offsets = window.get_offsets();
+                let word_ids = window.get_word_ids();
+                rows.axis_iter(Axis(0))
+                    .take(window.len())
                     .enumerate()
-                    .max_by(|(_, a), (_, b)| a.total_cmp(b))
-                    .map(|(index, _)| index)
-                    .unwrap();
-                let label = id2label[&label_indices.to_string()].clone();
-                if label.1 {
-                    let offset = offsets[i];
-                    if offset.0 != offset.1 {
-                        replacements.push((offset.0, offset.1, label.0));
-                    }
-                }
-                //dbg!(r4);
-            });
-
-        self.replace_words(text, replacements, items)
+                    .map(|(i, x)| {
+                        let result_exp = x.mapv(f32::exp);
+                        let results_exp_sum = result_exp.sum();
+                        TokenPrediction {
+                            start: offsets[i].0,
+                            end: offsets[i].1,
+                            word_id: word_ids[i],
+                            scores: result_exp.iter().map(|v| v / results_exp_sum).collect(),
+                        }
+                    })
+                    .collect()
+            })
+            .collect())
     }
+}
 
-    fn replace_words(
-        &self,
-        text_in: &str,
-        replacements: Vec<(usize, usize, String)>,
-        items: Option<HashMap<String, String>>,
-    ) -> Result<ReplaceResult> {
-        let mut text = text_in.to_string();
-        let mut offset: isize = 0;
-        let mut replaced_words: HashMap<String, String> = match items {
-            Some(it) => it,
-            None => HashMap::new(),
-        };
-        let mut replaced_words_counter = HashMap::new();
+#[derive(Deserialize)]
+struct ModelConfig {
+    id2label: HashMap<String, String>,
+}
 
-        for replacement in replacements {
-            let (mut start, mut end, word) = replacement;
-            start = (start as isize + offset) as usize;
-            end = (end as isize + offset) as usize;
+/// Reads the label map from a Hugging Face `config.json`. Labels other than `O` are
+/// anonymized if their entity type (the label without `B-`/`I-`) or the label itself
+/// is listed in `entities`, all of them without `entities`.
+pub fn id2label_from_config(
+    path: &Path,
+    entities: Option<&[String]>,
+) -> Result<HashMap<String, (String, bool)>> {
+    let config: ModelConfig = serde_json::from_str(&fs::read_to_string(path)?)?;
+    Ok(config
+        .id2label
+        .into_iter()
+        .map(|(id, label)| {
+            let entity_type = match label.split_once('-') {
+                Some(("B" | "I", t)) => t,
+                _ => label.as_str(),
+            };
+            let anonymize = label != "O"
+                && entities.map_or(true, |e| e.iter().any(|e| e == entity_type || *e == label));
+            (id, (label, anonymize))
+        })
+        .collect())
+}
 
-            if end > text.len() || start > end {
-                return Err(anyhow!("Invalid range"));
-            }
+/// Turns token predictions into entities. With `None` every token labeled as an entity
+/// is returned with its raw tag, other strategies merge B-/I- sequences into spans
+/// labeled with the entity type, scored with the mean of their tokens.
+pub fn aggregate_predictions(
+    text: &str,
+    predictions: &[TokenPrediction],
+    id2label: &HashMap<String, (String, bool)>,
+    strategy: &AggregationStrategy,
+) -> Vec<Entity> {
+    let argmax = |scores: &[f32]| {
+        scores
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(index, score)| (index, *score))
+            .unwrap()
+    };
+    let predictions = predictions.iter().filter(|p| p.start != p.end);
 
-            let old_word = text[start..end].to_string();
+    // (start, end, label index, score) of tokens or whole words
+    let units: Vec<(usize, usize, usize, f32)> = match strategy {
+        AggregationStrategy::None | AggregationStrategy::Simple => predictions
+            .map(|p| {
+                let (index, score) = argmax(&p.scores);
+                (p.start, p.end, index, score)
+            })
+            .collect(),
+        _ => {
+            let mut words: Vec<Vec<&TokenPrediction>> = Vec::new();
+            for p in predictions {
+                match words.last_mut() {
+                    Some(word) if p.word_id.is_some() && word[0].word_id == p.word_id => {
+                        word.push(p)
+                    }
+                    _ => words.push(vec![p]),
+                }
+            }
+            words
+                .iter()
+                .map(|word| {
+                    let (index, score) = match strategy {
+                        AggregationStrategy::Average => {
+                            let mut scores = vec![0.0; word[0].scores.len()];
+                            word.iter().for_each(|p| {
+                                scores.iter_mut().zip(&p.scores).for_each(|(s, v)| *s += v)
+                            });
+                            let n = word.len() as f32;
+                            argmax(&scores.iter().map(|s| s / n).collect::<Vec<_>>())
+                        }
+                        AggregationStrategy::Max => word
+                            .iter()
+                            .map(|p| argmax(&p.scores))
+                            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                            .unwrap(),
+                        _ => argmax(&word[0].scores),
+                    };
+                    (word[0].start, word.last().unwrap().end, index, score)
+                })
+                .collect()
+        }
+    };
 
-            let existing_item = replaced_words.iter().find(|(_, v)| *v == &old_word);
-            let mut word_rep = String::new();
-            match existing_item {
-                Some((k, _v)) => {
-                    word_rep.push_str(k);
+    let mut entities = Vec::new();
+    // (start, end, entity type, scores) of the group being built
+    let mut group: Option<(usize, usize, String, Vec<f32>)> = None;
+    for (start, end, index, score) in units {
+        let (label, is_entity) = &id2label[&index.to_string()];
+        if !is_entity {
+            if let Some(g) = group.take() {
+                entities.push(group_entity(text, g));
+            }
+            continue;
+        }
+        if *strategy == AggregationStrategy::None {
+            entities.push(Entity::new(text, label, start, end, "ner", Some(score)));
+            continue;
+        }
+        let (is_begin, entity_type) = match label.split_once('-') {
+            Some(("B", t)) => (true, t),
+            Some(("I", t)) => (false, t),
+            _ => (false, label.as_str()),
+        };
+        match group.as_mut() {
+            Some(g) if !is_begin && g.2 == entity_type => {
+                g.1 = end;
+                g.3.push(score);
+            }
+            _ => {
+                if let Some(g) = group.replace((start, end, entity_type.to_string(), vec![score])) {
+                    entities.push(group_entity(text, g));
                 }
-                None => {
-                    replaced_words_counter.insert(
-                        word.to_string(),
-                        if replaced_words_counter.contains_key(&word) {
-                            replaced_words_counter[&word] + 1
-                        } else {
-                            0
-                        },
-                    );
-                    let idx = replaced_words_counter[&word];
-                    word_rep.push_str(&word);
-                    word_rep.push_str(&idx.to_string());
+            }
+        }
+    }
+    if let Some(g) = group {
+        entities.push(group_entity(text, g));
+    }
+    entities
+}
+
+/// Merges predictions of overlapping windows into one sequence ordered by offsets.
+/// A token seen in several windows keeps the prediction of the window where it is
+/// farthest from the window edge, where the model has the most context.
+pub fn merge_windows(windows: Vec<Vec<TokenPrediction>>) -> Vec<TokenPrediction> {
+    let mut merged: BTreeMap<(usize, usize), (usize, TokenPrediction)> = BTreeMap::new();
+    for window in windows {
+        let tokens: Vec<TokenPrediction> =
+            window.into_iter().filter(|p| p.start != p.end).collect();
+        let len = tokens.len();
+        for (i, p) in tokens.into_iter().enumerate() {
+            let context = i.min(len - 1 - i);
+            match merged.get(&(p.start, p.end)) {
+                Some((c, _)) if *c >= context => {}
+                _ => {
+                    merged.insert((p.start, p.end), (context, p));
                 }
             }
+        }
+    }
+    merged.into_values().map(|(_, p)| p).collect()
+}
 
-            text.replace_range(start..end, &word_rep);
+/// Keeps entities scored at least the threshold of their label, or `min_score`
+/// for labels without one.
+pub fn filter_scores(
+    entities: Vec<Entity>,
+    min_score: Option<f32>,
+    thresholds: &HashMap<String, f32>,
+) -> Vec<Entity> {
+    entities
+        .into_iter()
+        .filter(|e| match thresholds.get(&e.label).copied().or(min_score) {
+            Some(threshold) => e.score.unwrap_or(1.0) >= threshold,
+            None => true,
+        })
+        .collect()
+}
 
-            replaced_words.insert(word_rep.to_string(), old_word);
-            let len = end - start;
-            offset += word_rep.len() as isize - len as isize;
+/// Renames entity labels found in `rename` as is or by their entity type.
+pub fn rename_labels(entities: &mut [Entity], rename: &HashMap<String, String>) {
+    for entity in entities.iter_mut() {
+        let entity_type = match entity.label.split_once('-') {
+            Some(("B" | "I", t)) => t,
+            _ => entity.label.as_str(),
+        };
+        if let Some(name) = rename
+            .get(&entity.label)
+            .or_else(|| rename.get(entity_type))
+        {
+            entity.label = name.to_string();
         }
+    }
+}
 
-        Ok(ReplaceResult {
-            text,
-            items: replaced_words,
-        })
+fn group_entity(text: &str, group: (usize, usize, String, Vec<f32>)) -> Entity {
+    let (start, end, label, scores) = group;
+    let score = scores.iter().sum::<f32>() / scores.len() as f32;
+    Entity::new(text, &label, start, end, "ner", Some(score))
+}
+
+impl Detector for NerAnonymizer {
+    fn detect(&self, text: &str) -> Result<Vec<Entity>> {
+        self.detect_entities(text)
+    }
+
+    fn detect_batch(&self, texts: &[&str]) -> Result<Vec<Vec<Entity>>> {
+        self.detect_entities_batch(texts)
     }
 }
 
@@ -193,4 +459,17 @@ impl Anonymizer for NerAnonymizer {
     ) -> Result<ReplaceResult> {
         self.replace_matches(text, replacement, items)
     }
+
+    fn anonymize_batch(
+        &self,
+        texts: &[&str],
+        replacement: Option<&str>,
+    ) -> Result<Vec<ReplaceResult>> {
+        let replacer = Replacer::default();
+        texts
+            .iter()
+            .zip(self.detect_entities_batch(texts)?)
+            .map(|(text, entities)| replacer.replace(text, &entities, replacement, None))
+            .collect()
+    }
 }
@@ -0,0 +1,86 @@
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Text normalization applied before keyword matching. Offsets of the normalized text
+/// can be mapped back to the original text, so the original slice is what gets replaced.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Normalizer {
+    pub ignore_case: bool,
+    pub nfkc: bool,
+    pub fold_diacritics: bool,
+}
+
+impl Normalizer {
+    pub fn new(ignore_case: bool, nfkc: bool, fold_diacritics: bool) -> Self {
+        Normalizer {
+            ignore_case,
+            nfkc,
+            fold_diacritics,
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        !(self.ignore_case || self.nfkc || self.fold_diacritics)
+    }
+
+    pub fn normalize(&self, text: &str) -> String {
+        self.normalize_with_offsets(text).0
+    }
+
+    /// Normalized text and, for every byte of it plus its end, the byte offset in the
+    /// original text. A char is normalized together with its combining marks, so the
+    /// offsets always point at char boundaries of the original text.
+    pub fn normalize_with_offsets(&self, text: &str) -> (String, Vec<usize>) {
+        if self.is_identity() {
+            return (text.to_string(), (0..=text.len()).collect());
+        }
+
+        let mut normalized = String::with_capacity(text.len());
+        let mut offsets = Vec::with_capacity(text.len() + 1);
+        let mut chars = text.char_indices().peekable();
+        while let Some((start, ch)) = chars.next() {
+            let mut end = start + ch.len_utf8();
+            while let Some((i, mark)) = chars.next_if(|(_, c)| is_combining_mark(*c)) {
+                end = i + mark.len_utf8();
+            }
+            let segment = self.normalize_segment(&text[start..end]);
+            offsets.extend(std::iter::repeat(start).take(segment.len()));
+            normalized.push_str(&segment);
+        }
+        offsets.push(text.len());
+        (normalized, offsets)
+    }
+
+    fn normalize_segment(&self, segment: &str) -> String {
+        let mut segment = if self.nfkc {
+            segment.nfkc().collect()
+        } else {
+            segment.to_string()
+        };
+        if self.fold_diacritics {
+            segment = segment
+                .nfd()
+                .filter(|c| !is_combining_mark(*c))
+                .map(|c| match c {
+                    'ł' => "l".to_string(),
+                    'Ł' => "L".to_string(),
+                    'đ' => "d".to_string(),
+                    'Đ' => "D".to_string(),
+                    'ø' => "o".to_string(),
+                    'Ø' => "O".to_string(),
+                    'ı' => "i".to_string(),
+                    'ß' => "ss".to_string(),
+                    'æ' => "ae".to_string(),
+                    'Æ' => "AE".to_string(),
+                    'œ' => "oe".to_string(),
+                    'Œ' => "OE".to_string(),
+                    c => c.to_string(),
+                })
+                .collect();
+        }
+        if self.ignore_case {
+            segment = segment.to_lowercase();
+        }
+        segment
+    }
+}
@@ -0,0 +1,289 @@
+use crate::anonymizer::surrogate::Surrogate;
+use crate::config::{HashAlgorithm, OperatorConfig};
+use aes::Aes256;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use fpe::ff1::{FlexibleNumeralString, FF1};
+use hmac::{Hmac, Mac};
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+
+const NONCE_LEN: usize = 12;
+const FPE_MIN_DIGITS: usize = 6;
+
+/// Operation performed on a detected value instead of the default placeholder.
+#[derive(Debug, Clone)]
+pub enum Operator {
+    Replace,
+    Redact,
+    Mask {
+        masking_char: char,
+        keep_last: usize,
+    },
+    Hash {
+        algorithm: HashAlgorithm,
+        salt: String,
+    },
+    Custom {
+        value: String,
+    },
+    Encrypt(Keyring),
+    Fpe(FormatPreserving),
+    Surrogate(Surrogate),
+}
+
+impl Operator {
+    pub fn new(config: OperatorConfig) -> Result<Self> {
+        let operator = match config {
+            OperatorConfig::Replace => Operator::Replace,
+            OperatorConfig::Redact => Operator::Redact,
+            OperatorConfig::Mask {
+                masking_char,
+                keep_last,
+            } => Operator::Mask {
+                masking_char: masking_char.unwrap_or('*'),
+                keep_last: keep_last.unwrap_or(0),
+            },
+            OperatorConfig::Hash { algorithm, salt } => {
+                let algorithm = algorithm.unwrap_or_default();
+                if algorithm == HashAlgorithm::HmacSha256 && salt.is_none() {
+                    return Err(anyhow!("HMAC requires salt"));
+                }
+                // an unsalted digest of a phone number or an id is reversed by hashing
+                // all candidates, without a configured salt a random one is used
+                let salt = salt.unwrap_or_else(|| {
+                    let mut salt = [0u8; 32];
+                    OsRng.fill_bytes(&mut salt);
+                    hex::encode(salt)
+                });
+                Operator::Hash { algorithm, salt }
+            }
+            OperatorConfig::Custom { value } => Operator::Custom { value },
+            OperatorConfig::Encrypt { key_id, keys } => {
+                Operator::Encrypt(Keyring::new(&key_id, &keys)?)
+            }
+            OperatorConfig::Fpe { key, tweak } => {
+                Operator::Fpe(FormatPreserving::new(&key, tweak.as_deref())?)
+            }
+            OperatorConfig::Surrogate { kind, locale, seed } => {
+                Operator::Surrogate(Surrogate::new(kind, locale, seed))
+            }
+        };
+        Ok(operator)
+    }
+
+    /// Operators whose output is mapped back to the original value through `items`.
+    pub fn stores_items(&self) -> bool {
+        matches!(
+            self,
+            Operator::Replace | Operator::Fpe(_) | Operator::Surrogate(_)
+        )
+    }
+
+    /// Applies non placeholder operators, `Replace` is handled by `Replacer`.
+    pub fn apply(&self, value: &str) -> Result<String> {
+        let result = match self {
+            Operator::Replace => return Err(anyhow!("Replace operator uses placeholders")),
+            Operator::Redact => String::new(),
+            Operator::Mask {
+                masking_char,
+                keep_last,
+            } => {
+                let total = value.chars().filter(|c| c.is_alphanumeric()).count();
+                let mut seen = 0;
+                value
+                    .chars()
+                    .map(|c| {
+                        if !c.is_alphanumeric() {
+                            return c;
+                        }
+                        seen += 1;
+                        if seen + keep_last > total {
+                            c
+                        } else {
+                            *masking_char
+                        }
+                    })
+                    .collect()
+            }
+            Operator::Hash { algorithm, salt } => match algorithm {
+                HashAlgorithm::Sha256 => {
+                    let mut hasher = Sha256::new();
+                    hasher.update(salt.as_bytes());
+                    hasher.update(value.as_bytes());
+                    hex::encode(hasher.finalize())
+                }
+                HashAlgorithm::HmacSha256 => {
+                    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(salt.as_bytes())?;
+                    mac.update(value.as_bytes());
+                    hex::encode(mac.finalize().into_bytes())
+                }
+            },
+            Operator::Custom { value } => value.to_string(),
+            Operator::Encrypt(keyring) => keyring.encrypt(value)?,
+            Operator::Fpe(fpe) => fpe.encrypt(value)?,
+            Operator::Surrogate(surrogate) => surrogate.generate(value, 0),
+        };
+        Ok(result)
+    }
+}
+
+/// Encryption keys addressed by id. New tokens use the active key, any known key
+/// can decrypt, so keys can be rotated without breaking older tokens.
+#[derive(Clone)]
+pub struct Keyring {
+    key_id: String,
+    ciphers: HashMap<String, Aes256Gcm>,
+    token: Regex,
+}
+
+impl fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keyring")
+            .field("key_id", &self.key_id)
+            .field("keys", &self.ciphers.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Keyring {
+    pub fn new(key_id: &str, keys: &HashMap<String, String>) -> Result<Self> {
+        let key_id_regex = Regex::new(r"^[A-Za-z0-9_\-]+$")?;
+        let mut ciphers = HashMap::new();
+        for (id, key) in keys {
+            if !key_id_regex.is_match(id) {
+                return Err(anyhow!("Invalid key id: {id}"));
+            }
+            let key = STANDARD.decode(key.trim())?;
+            let cipher = Aes256Gcm::new_from_slice(&key)
+                .map_err(|_| anyhow!("Key {id} must have 32 bytes"))?;
+            ciphers.insert(id.to_string(), cipher);
+        }
+        if !ciphers.contains_key(key_id) {
+            return Err(anyhow!("Unknown key id: {key_id}"));
+        }
+
+        Ok(Keyring {
+            key_id: key_id.to_string(),
+            ciphers,
+            token: Regex::new(r"<enc:(?P<kid>[A-Za-z0-9_\-]+):(?P<data>[A-Za-z0-9_\-]+)>")?,
+        })
+    }
+
+    /// Encrypts value into `<enc:{key_id}:{base64url(nonce|ciphertext)}>` token.
+    pub fn encrypt(&self, value: &str) -> Result<String> {
+        let cipher = &self.ciphers[&self.key_id];
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, value.as_bytes())
+            .map_err(|_| anyhow!("Encryption failed"))?;
+        let mut data = nonce.to_vec();
+        data.extend(ciphertext);
+        Ok(format!(
+            "<enc:{}:{}>",
+            self.key_id,
+            URL_SAFE_NO_PAD.encode(data)
+        ))
+    }
+
+    pub fn decrypt(&self, token: &str) -> Result<String> {
+        let captures = self
+            .token
+            .captures(token)
+            .filter(|c| c[0].len() == token.len())
+            .ok_or(anyhow!("Invalid token"))?;
+        let cipher = self
+            .ciphers
+            .get(&captures["kid"])
+            .ok_or(anyhow!("Unknown key id: {}", &captures["kid"]))?;
+        let data = URL_SAFE_NO_PAD.decode(&captures["data"])?;
+        if data.len() < NONCE_LEN {
+            return Err(anyhow!("Invalid token"));
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let value = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("Decryption failed"))?;
+        Ok(String::from_utf8(value)?)
+    }
+
+    pub fn find_at<'t>(&self, text: &'t str, start: usize) -> Option<regex::Match<'t>> {
+        self.token.find_at(text, start)
+    }
+}
+
+/// FF1 (NIST SP 800-38G) encryption of the digits in a value. Digit count and all
+/// separators are kept, so the result passes the same format checks as the input.
+#[derive(Clone)]
+pub struct FormatPreserving {
+    key: Vec<u8>,
+    tweak: Vec<u8>,
+}
+
+impl fmt::Debug for FormatPreserving {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FormatPreserving").finish_non_exhaustive()
+    }
+}
+
+impl FormatPreserving {
+    pub fn new(key: &str, tweak: Option<&str>) -> Result<Self> {
+        let key = STANDARD.decode(key.trim())?;
+        if key.len() != 32 {
+            return Err(anyhow!("FPE key must have 32 bytes"));
+        }
+        Ok(FormatPreserving {
+            key,
+            tweak: tweak.unwrap_or_default().as_bytes().to_vec(),
+        })
+    }
+
+    /// Values with fewer than 6 digits can't be encrypted.
+    pub fn supports(&self, value: &str) -> bool {
+        value.chars().filter(|c| c.is_ascii_digit()).count() >= FPE_MIN_DIGITS
+    }
+
+    pub fn encrypt(&self, value: &str) -> Result<String> {
+        self.transform(value, true)
+    }
+
+    pub fn decrypt(&self, value: &str) -> Result<String> {
+        self.transform(value, false)
+    }
+
+    fn transform(&self, value: &str, encrypt: bool) -> Result<String> {
+        let digits: Vec<u16> = value
+            .chars()
+            .filter_map(|c| c.to_digit(10))
+            .map(|d| d as u16)
+            .collect();
+        if digits.len() < FPE_MIN_DIGITS {
+            return Err(anyhow!("FPE needs at least {FPE_MIN_DIGITS} digits"));
+        }
+
+        let ff1 = FF1::<Aes256>::new(&self.key, 10).map_err(|e| anyhow!("{e}"))?;
+        let numerals = FlexibleNumeralString::from(digits);
+        let transformed: Vec<u16> = if encrypt {
+            ff1.encrypt(&self.tweak, &numerals)
+        } else {
+            ff1.decrypt(&self.tweak, &numerals)
+        }
+        .map_err(|e| anyhow!("{e}"))?
+        .into();
+
+        let mut transformed = transformed.into_iter();
+        Ok(value
+            .chars()
+            .map(|c| match c.to_digit(10) {
+                Some(_) => char::from_digit(transformed.next().unwrap() as u32, 10).unwrap(),
+                None => c,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,121 @@
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use std::collections::HashMap;
+
+pub const DEFAULT_PLACEHOLDER: &str = "{label}{n}";
+
+/// Placeholder syntax built from a template such as `<{label}_{n}>` or `[[{label}:{n}]]`.
+#[derive(Debug, Clone)]
+pub struct Placeholder {
+    template: String,
+    regex: Regex,
+}
+
+impl Placeholder {
+    pub fn new(template: &str) -> Result<Self> {
+        if !template.contains("{n}") {
+            return Err(anyhow!("Placeholder template must contain {{n}}"));
+        }
+
+        let mut pattern = String::new();
+        let mut rest = template;
+        while let Some(pos) = rest.find('{') {
+            pattern.push_str(&regex::escape(&rest[..pos]));
+            rest = &rest[pos..];
+            if let Some(r) = rest.strip_prefix("{label}") {
+                pattern.push_str(r"(?P<label>[\p{L}\p{N}_\-.]+?)");
+                rest = r;
+            } else if let Some(r) = rest.strip_prefix("{n}") {
+                pattern.push_str(r"(?P<n>\d+)");
+                rest = r;
+            } else {
+                pattern.push_str(&regex::escape("{"));
+                rest = &rest[1..];
+            }
+        }
+        pattern.push_str(&regex::escape(rest));
+
+        Ok(Placeholder {
+            template: template.to_string(),
+            regex: Regex::new(&pattern)?,
+        })
+    }
+
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    pub fn format(&self, label: &str, n: usize) -> String {
+        self.template
+            .replace("{label}", label)
+            .replace("{n}", &n.to_string())
+    }
+
+    pub fn is_placeholder(&self, value: &str) -> bool {
+        self.regex
+            .find(value)
+            .is_some_and(|m| m.start() == 0 && m.end() == value.len())
+    }
+
+    pub fn find_at<'t>(&self, text: &'t str, start: usize) -> Option<regex::Match<'t>> {
+        self.regex.find_at(text, start)
+    }
+
+    /// Restores placeholders found in `text` in a single left-to-right pass.
+    /// Tokens which follow the syntax but are unknown to `items` are kept as they are.
+    pub fn deanonymize(&self, text: &str, items: &HashMap<String, String>) -> String {
+        let finder: TokenFinder = Box::new(|pos| {
+            self.find_at(text, pos)
+                .map(|m| (m.start(), m.end(), items.get(m.as_str()).cloned()))
+        });
+        scan_tokens(text, &[finder])
+    }
+}
+
+/// Next candidate token starting at or after the given position as `(start, end, value)`,
+/// the value is `None` when the token can't be resolved.
+pub type TokenFinder<'a> = Box<dyn Fn(usize) -> Option<(usize, usize, Option<String>)> + 'a>;
+
+/// Rewrites tokens in a single left-to-right pass. The next candidate of every finder is
+/// kept until the scan moves past its start. The earliest resolved candidate is replaced,
+/// unresolved candidates are kept and scanning resumes at the next character.
+pub fn scan_tokens(text: &str, finders: &[TokenFinder]) -> String {
+    let mut next: Vec<_> = finders.iter().map(|find| find(0)).collect();
+    let mut result = String::with_capacity(text.len());
+    let mut pos = 0;
+    loop {
+        for (find, candidate) in finders.iter().zip(next.iter_mut()) {
+            if candidate.as_ref().is_some_and(|(s, _, _)| *s < pos) {
+                *candidate = find(pos);
+            }
+        }
+        let Some(start) = next.iter().flatten().map(|(s, _, _)| *s).min() else {
+            break;
+        };
+        let resolved = next
+            .iter()
+            .flatten()
+            .filter(|(s, _, _)| *s == start)
+            .find_map(|(_, end, value)| value.clone().map(|v| (*end, v)));
+        match resolved {
+            Some((end, value)) => {
+                result.push_str(&text[pos..start]);
+                result.push_str(&value);
+                pos = end;
+            }
+            None => {
+                let next = start + text[start..].chars().next().map_or(1, char::len_utf8);
+                result.push_str(&text[pos..next]);
+                pos = next;
+            }
+        }
+    }
+    result.push_str(&text[pos..]);
+    result
+}
+
+impl Default for Placeholder {
+    fn default() -> Self {
+        Placeholder::new(DEFAULT_PLACEHOLDER).unwrap()
+    }
+}
@@ -1,36 +1,154 @@
-use crate::anonymizer::{Anonymizer, ReplaceResult};
+use crate::anonymizer::context::Context;
+use crate::anonymizer::replacer::Replacer;
+use crate::anonymizer::validator::validate;
+use crate::anonymizer::{resolve_overlaps, Anonymizer, Detector, Entity, ReplaceResult};
+use crate::config::{OverlapStrategy, RegexEngine, ValidatorKind};
 use anyhow::{anyhow, Result};
 use regex::Regex;
+use regex_automata::{meta, Anchored, Input, MatchKind, PatternSet};
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{self, BufRead};
+use std::time::{Duration, Instant};
+
+const DEFAULT_BACKTRACK_LIMIT: usize = 1_000_000;
+const DEFAULT_TIME_LIMIT: Duration = Duration::from_secs(1);
 
 #[derive(Debug, Clone)]
 pub struct RegexAnonymizer {
-    regex_patterns: Vec<Regex>,
+    regex_patterns: Vec<String>,
+    /// All patterns compiled together, finds the next match of any pattern.
+    scanner: meta::Regex,
+    /// The same patterns reporting every pattern which matches at a position.
+    starts: meta::Regex,
+    /// Patterns of the backtracking engine, used with `RegexEngine::Fancy`.
+    fancy_patterns: Vec<fancy_regex::Regex>,
+    engine: RegexEngine,
+    backtrack_limit: usize,
+    time_limit: Duration,
     replacement: Option<String>,
+    group: usize,
+    overlap: OverlapStrategy,
+    validator: Option<ValidatorKind>,
+    invalid_score: Option<f32>,
+    context: Option<Context>,
 }
 
 impl RegexAnonymizer {
     pub fn new(replacement: Option<String>) -> Self {
+        let (scanner, starts) = compile_patterns::<&str>(&[]).unwrap();
         RegexAnonymizer {
             regex_patterns: Vec::new(),
+            scanner,
+            starts,
+            fancy_patterns: Vec::new(),
+            engine: RegexEngine::default(),
+            backtrack_limit: DEFAULT_BACKTRACK_LIMIT,
+            time_limit: DEFAULT_TIME_LIMIT,
             replacement,
+            group: 0,
+            overlap: OverlapStrategy::default(),
+            validator: None,
+            invalid_score: None,
+            context: None,
         }
     }
 
+    /// Numbered capture group which is anonymized, the rest of the match is kept as
+    /// context (default 0, the whole match). Patterns with named groups anonymize
+    /// the named groups instead, labeled with the group names, the group is not used
+    /// for them.
+    pub fn set_group(&mut self, group: usize) {
+        self.group = group;
+    }
+
+    /// Engine used for patterns added afterwards. `Fancy` supports lookaround and
+    /// backreferences, each search is limited to `backtrack_limit` backtracking steps
+    /// and a text has to be scanned within `time_limit`, otherwise detection fails.
+    /// The time limit is only checked between matches, a single search is bounded by
+    /// `backtrack_limit` alone.
+    pub fn set_engine(
+        &mut self,
+        engine: RegexEngine,
+        backtrack_limit: Option<usize>,
+        time_limit: Option<Duration>,
+    ) {
+        self.engine = engine;
+        self.backtrack_limit = backtrack_limit.unwrap_or(DEFAULT_BACKTRACK_LIMIT);
+        self.time_limit = time_limit.unwrap_or(DEFAULT_TIME_LIMIT);
+    }
+
+    /// How overlapping matches of different patterns are resolved, with `Priority`
+    /// (default) the earlier pattern wins.
+    pub fn set_overlap(&mut self, overlap: OverlapStrategy) {
+        self.overlap = overlap;
+    }
+
+    /// Matches failing the check digit validation are dropped, or kept with
+    /// `invalid_score` when it is set.
+    pub fn set_validator(&mut self, validator: ValidatorKind, invalid_score: Option<f32>) {
+        self.validator = Some(validator);
+        self.invalid_score = invalid_score;
+    }
+
+    /// Scores matches by the words around them, see [`Context::apply`].
+    pub fn set_context(&mut self, context: Context) {
+        self.context = Some(context);
+    }
+
     pub fn add_regex_patterns_file(&mut self, path: &str) -> Result<()> {
         let file = File::open(path)?;
-        io::BufReader::new(file)
+        let patterns = io::BufReader::new(file)
             .lines()
-            .try_for_each(|word| -> Result<()> { self.add_regex_pattern(&word?) })?;
-
-        Ok(())
+            .collect::<io::Result<Vec<String>>>()?;
+        self.add_regex_patterns(&patterns)
     }
 
     pub fn add_regex_pattern(&mut self, pattern: &str) -> Result<()> {
-        let regex = Regex::new(pattern)?;
-        self.regex_patterns.push(regex);
+        self.add_regex_patterns(&[pattern])
+    }
+
+    /// Adds the patterns only when all of them compile and have the configured group.
+    pub fn add_regex_patterns<S: AsRef<str>>(&mut self, patterns: &[S]) -> Result<()> {
+        let mut regex_patterns = vec![];
+        let mut fancy_patterns = vec![];
+        for pattern in patterns {
+            let pattern = pattern.as_ref();
+            let (named, captures_len) = match self.engine {
+                RegexEngine::Standard => {
+                    let regex = Regex::new(pattern)?;
+                    let groups = (
+                        has_named_groups(regex.capture_names()),
+                        regex.captures_len(),
+                    );
+                    regex_patterns.push(pattern.to_string());
+                    groups
+                }
+                RegexEngine::Fancy => {
+                    let regex = fancy_regex::RegexBuilder::new(pattern)
+                        .backtrack_limit(self.backtrack_limit)
+                        .build()?;
+                    let groups = (
+                        has_named_groups(regex.capture_names()),
+                        regex.captures_len(),
+                    );
+                    fancy_patterns.push(regex);
+                    groups
+                }
+            };
+            if !named && self.group >= captures_len {
+                return Err(anyhow!(
+                    "Pattern {pattern} has no capture group {}",
+                    self.group
+                ));
+            }
+        }
+        if !regex_patterns.is_empty() {
+            let all = [self.regex_patterns.as_slice(), &regex_patterns].concat();
+            (self.scanner, self.starts) = compile_patterns(&all)?;
+            self.regex_patterns = all;
+        }
+        self.fancy_patterns.extend(fancy_patterns);
         Ok(())
     }
 
@@ -40,54 +158,160 @@ impl RegexAnonymizer {
         replacement: Option<&str>,
         items: Option<HashMap<String, String>>,
     ) -> Result<ReplaceResult> {
-        let mut result = text.to_string();
-        let mut items: HashMap<String, String> = match items {
-            Some(it) => it,
-            None => HashMap::new(),
-        };
-        let mut idx = 0;
-
-        let base_replacement = if replacement.is_some() {
-            replacement.ok_or(anyhow!("SET REPLACEMENT"))?.to_string()
-        } else {
-            self.replacement.clone().ok_or(anyhow!("SET REPLACEMENT"))?
-        };
-
-        for pattern in &self.regex_patterns {
-            let mut it = pattern.find_iter(&result).enumerate().peekable();
-            if it.peek().is_some() {
-                let mut new = String::with_capacity(result.len());
-                let mut last_match = 0;
-                for (_i, m) in it {
-                    let start = m.start();
-                    new.push_str(&result[last_match..start]);
-                    last_match = m.end();
-                    let mut rep: String = base_replacement.to_string();
-                    let item_value = result[start..last_match].to_string();
-                    let existing_item = items.iter().find(|(_, v)| *v == &item_value);
-                    match existing_item {
-                        Some((k, _v)) => {
-                            rep = k.to_string();
-                        }
-                        None => {
-                            rep.push_str(&idx.to_string());
-                            items.insert(rep.to_string(), item_value);
-                            idx += 1;
-                        }
-                    }
-
-                    new.push_str(&rep.to_string());
+        let entities = self.detect_regex_matches(text, replacement)?;
+        Replacer::default().replace(text, &entities, None, items)
+    }
+
+    /// Matches labeled with the `replacement` when given, otherwise with their group
+    /// name or the stage name.
+    fn detect_regex_matches(&self, text: &str, replacement: Option<&str>) -> Result<Vec<Entity>> {
+        let label = replacement
+            .or(self.replacement.as_deref())
+            .ok_or(anyhow!("SET REPLACEMENT"))?;
+        let mut candidates = self.fancy_matches(text, label, replacement)?;
+        candidates.extend(self.standard_matches(text, label, replacement));
+        candidates.retain(|(_, e)| e.start < e.end);
+        if let Some(validator) = self.validator {
+            candidates.retain_mut(|(_, e)| {
+                if validate(validator, &e.text) {
+                    return true;
+                }
+                e.score = self.invalid_score;
+                e.score.is_some()
+            });
+        }
+        let entities = resolve_overlaps(candidates, &self.overlap);
+        Ok(match &self.context {
+            Some(context) => context.apply(text, entities),
+            None => entities,
+        })
+    }
+
+    /// Matches of the standard patterns found in one pass. Every position where a pattern
+    /// matches is visited once and only the patterns matching there are run, anchored,
+    /// so each pattern reports the same non-overlapping matches as searched on its own.
+    fn standard_matches(
+        &self,
+        text: &str,
+        label: &str,
+        replacement: Option<&str>,
+    ) -> Vec<(usize, Entity)> {
+        let mut candidates = vec![];
+        let mut captures = self.scanner.create_captures();
+        let mut matching = PatternSet::new(self.starts.pattern_len());
+        let mut last_end = vec![0; self.regex_patterns.len()];
+        let mut pos = 0;
+        while let Some(m) = self.scanner.search(&Input::new(text).range(pos..)) {
+            let start = m.start();
+            matching.clear();
+            let at_start = Input::new(text).range(start..).anchored(Anchored::Yes);
+            self.starts
+                .which_overlapping_matches(&at_start, &mut matching);
+            for pid in matching.iter() {
+                let idx = pid.as_usize();
+                if start < last_end[idx] {
+                    continue;
+                }
+                let input = Input::new(text)
+                    .range(start..)
+                    .anchored(Anchored::Pattern(pid));
+                self.scanner.search_captures(&input, &mut captures);
+                let Some(m) = captures.get_match() else {
+                    continue;
+                };
+                last_end[idx] = m.end();
+                let names = self.scanner.group_info().pattern_names(pid);
+                if has_named_groups(self.scanner.group_info().pattern_names(pid)) {
+                    candidates.extend(names.enumerate().filter_map(|(i, name)| {
+                        let (name, span) = (name?, captures.get_group(i)?);
+                        let label = replacement.unwrap_or(name);
+                        Some((
+                            idx,
+                            Entity::new(text, label, span.start, span.end, "regex", None),
+                        ))
+                    }));
+                } else if let Some(span) = captures.get_group(self.group) {
+                    candidates.push((
+                        idx,
+                        Entity::new(text, label, span.start, span.end, "regex", None),
+                    ));
                 }
-                new.push_str(&result[last_match..]);
+            }
+            if start == text.len() {
+                break;
+            }
+            pos = start + text[start..].chars().next().map_or(1, char::len_utf8);
+        }
+        candidates
+    }
 
-                result = new;
+    fn fancy_matches(
+        &self,
+        text: &str,
+        label: &str,
+        replacement: Option<&str>,
+    ) -> Result<Vec<(usize, Entity)>> {
+        let started = Instant::now();
+        let mut candidates = vec![];
+        // fancy patterns are added after the standard ones of the stage
+        let offset = self.regex_patterns.len();
+        for (idx, pattern) in self.fancy_patterns.iter().enumerate() {
+            let idx = offset + idx;
+            let named = has_named_groups(pattern.capture_names());
+            for captures in pattern.captures_iter(text) {
+                let captures = captures?;
+                self.check_time_limit(started, pattern)?;
+                if named {
+                    candidates.extend(pattern.capture_names().enumerate().filter_map(
+                        |(i, name)| {
+                            let (name, m) = (name?, captures.get(i)?);
+                            let label = replacement.unwrap_or(name);
+                            Some((
+                                idx,
+                                Entity::new(text, label, m.start(), m.end(), "regex", None),
+                            ))
+                        },
+                    ));
+                } else if let Some(m) = captures.get(self.group) {
+                    candidates.push((
+                        idx,
+                        Entity::new(text, label, m.start(), m.end(), "regex", None),
+                    ));
+                }
             }
+            self.check_time_limit(started, pattern)?;
         }
+        Ok(candidates)
+    }
 
-        Ok(ReplaceResult {
-            text: result,
-            items,
-        })
+    fn check_time_limit(&self, started: Instant, pattern: &fancy_regex::Regex) -> Result<()> {
+        if started.elapsed() > self.time_limit {
+            return Err(anyhow!(
+                "Pattern {} exceeded the time limit",
+                pattern.as_str()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Scanner reporting leftmost matches and the one reporting all patterns matching
+/// at a position, both over the same patterns.
+fn compile_patterns<S: AsRef<str>>(patterns: &[S]) -> Result<(meta::Regex, meta::Regex)> {
+    let scanner = meta::Regex::new_many(patterns)?;
+    let starts = meta::Regex::builder()
+        .configure(meta::Config::new().match_kind(MatchKind::All))
+        .build_many(patterns)?;
+    Ok((scanner, starts))
+}
+
+fn has_named_groups<'a>(mut names: impl Iterator<Item = Option<&'a str>>) -> bool {
+    names.any(|name| name.is_some())
+}
+
+impl Detector for RegexAnonymizer {
+    fn detect(&self, text: &str) -> Result<Vec<Entity>> {
+        self.detect_regex_matches(text, None)
     }
 }
 
@@ -0,0 +1,316 @@
+use crate::anonymizer::operator::Operator;
+use crate::anonymizer::placeholder::{scan_tokens, Placeholder, TokenFinder};
+use crate::anonymizer::surrogate::Surrogate;
+use crate::anonymizer::vault::Vault;
+use crate::anonymizer::{Entity, ReplaceResult};
+use anyhow::Result;
+use regex::Regex;
+use std::collections::HashMap;
+
+const MAX_SURROGATE_ATTEMPTS: usize = 32;
+
+/// Turns detected entities into anonymized text and restores it back.
+#[derive(Debug, Clone, Default)]
+pub struct Replacer {
+    pub placeholder: Placeholder,
+    pub operators: HashMap<String, Operator>,
+    pub stage_operators: HashMap<usize, Operator>,
+    pub vault: Option<Vault>,
+}
+
+impl Replacer {
+    pub fn new(
+        placeholder: Placeholder,
+        operators: HashMap<String, Operator>,
+        vault: Option<Vault>,
+    ) -> Self {
+        Replacer {
+            placeholder,
+            operators,
+            stage_operators: HashMap::new(),
+            vault,
+        }
+    }
+
+    /// Operator for all entities detected by the pipeline stage, it takes precedence
+    /// over the operator of their label.
+    pub fn set_stage_operator(&mut self, stage: usize, operator: Operator) {
+        self.stage_operators.insert(stage, operator);
+    }
+
+    fn operator(&self, entity: &Entity) -> Option<&Operator> {
+        entity
+            .stage
+            .and_then(|stage| self.stage_operators.get(&stage))
+            .or_else(|| self.operators.get(&entity.label))
+    }
+
+    /// Byte ranges of placeholders from `items` which are already present in the text.
+    pub fn protected_regions(
+        &self,
+        text: &str,
+        items: &HashMap<String, String>,
+    ) -> Vec<(usize, usize)> {
+        let mut regions: Vec<(usize, usize)> = items
+            .keys()
+            .filter(|k| !k.is_empty())
+            .flat_map(|k| {
+                text.match_indices(k.as_str())
+                    .map(|(i, m)| (i, i + m.len()))
+            })
+            .collect();
+        regions.sort();
+        regions
+    }
+
+    /// Replaces detected entities with placeholders. Values already present in `items`
+    /// reuse their placeholder, overlapping entities are skipped. Placeholders inserted by
+    /// earlier calls are left untouched so they can be deanonymized, entities overlapping
+    /// them are cut around them and the remaining parts are replaced.
+    /// Placeholders which already appear in the original text are never generated.
+    /// Other operators rewrite the value in place and only record it in `items` when
+    /// the operator is mapped back through them.
+    pub fn replace(
+        &self,
+        text: &str,
+        entities: &[Entity],
+        replacement: Option<&str>,
+        items: Option<HashMap<String, String>>,
+    ) -> Result<ReplaceResult> {
+        let mut items = items.unwrap_or_default();
+        let mut counters: HashMap<String, usize> = HashMap::new();
+        let protected = self.protected_regions(text, &items);
+        let mut sorted: Vec<Entity> = entities
+            .iter()
+            .flat_map(|e| split_protected(text, e, &protected))
+            .collect();
+        sorted.sort_by_key(|e| (e.start, e.end));
+
+        let mut result = String::with_capacity(text.len());
+        let mut last = 0;
+        for entity in sorted {
+            if entity.start < last || entity.start == entity.end {
+                continue;
+            }
+            let item_value = &text[entity.start..entity.end];
+            let label = replacement.unwrap_or(&entity.label);
+            let rep = match self.operator(&entity) {
+                Some(Operator::Replace) | None => {
+                    self.placeholder_for(text, label, item_value, &mut items, &mut counters)?
+                }
+                // too short for FPE, e.g. a 4 digit match of a loose pattern
+                Some(Operator::Fpe(fpe)) if !fpe.supports(item_value) => {
+                    self.placeholder_for(text, label, item_value, &mut items, &mut counters)?
+                }
+                Some(Operator::Surrogate(surrogate)) => {
+                    self.surrogate_for(surrogate, text, item_value, &mut items)
+                }
+                Some(operator) => {
+                    let rep = operator.apply(item_value)?;
+                    if operator.stores_items() {
+                        items.insert(rep.to_string(), item_value.to_string());
+                    }
+                    rep
+                }
+            };
+            result.push_str(&text[last..entity.start]);
+            result.push_str(&rep);
+            last = entity.end;
+        }
+        result.push_str(&text[last..]);
+
+        Ok(ReplaceResult {
+            text: result,
+            items,
+        })
+    }
+
+    /// Placeholder for the value: reused from `items` or the vault, otherwise the first
+    /// free index for the label.
+    fn placeholder_for(
+        &self,
+        text: &str,
+        label: &str,
+        value: &str,
+        items: &mut HashMap<String, String>,
+        counters: &mut HashMap<String, usize>,
+    ) -> Result<String> {
+        if let Some((k, _v)) = items.iter().find(|(_, v)| *v == value) {
+            return Ok(k.to_string());
+        }
+        if let Some(vault) = &self.vault {
+            if let Some(rep) = vault.placeholder(value)? {
+                items.insert(rep.to_string(), value.to_string());
+                return Ok(rep);
+            }
+        }
+
+        let rep = match &self.vault {
+            Some(vault) => {
+                let mut rep = self.placeholder.format(label, vault.next_index(label)?);
+                while items.contains_key(&rep) || text.contains(&rep) {
+                    rep = self.placeholder.format(label, vault.next_index(label)?);
+                }
+                vault.insert(&rep, value)?
+            }
+            None => {
+                let idx = counters.entry(label.to_string()).or_insert(0);
+                let mut rep = self.placeholder.format(label, *idx);
+                while items.contains_key(&rep) || text.contains(&rep) {
+                    *idx += 1;
+                    rep = self.placeholder.format(label, *idx);
+                }
+                *idx += 1;
+                rep
+            }
+        };
+        items.insert(rep.to_string(), value.to_string());
+        Ok(rep)
+    }
+
+    /// Surrogate for the value which is reused within `items` and never collides with
+    /// another value or with the original text. When the generated alternatives run out,
+    /// e.g. more distinct cities than the list has, a numbered surrogate is used.
+    fn surrogate_for(
+        &self,
+        surrogate: &Surrogate,
+        text: &str,
+        value: &str,
+        items: &mut HashMap<String, String>,
+    ) -> String {
+        if let Some((k, _v)) = items.iter().find(|(_, v)| *v == value) {
+            return k.to_string();
+        }
+        let taken = |rep: &String| items.contains_key(rep) || text.contains(rep.as_str());
+        let mut rep = (0..MAX_SURROGATE_ATTEMPTS)
+            .map(|attempt| surrogate.generate(value, attempt))
+            .find(|rep| !taken(rep));
+        if rep.is_none() {
+            let base = surrogate.generate(value, 0);
+            rep = (2..)
+                .map(|n| surrogate.with_suffix(&base, n))
+                .find(|rep| !taken(rep));
+        }
+        let rep = rep.unwrap_or_default();
+        items.insert(rep.to_string(), value.to_string());
+        rep
+    }
+
+    /// Restores placeholders and other values known to `items` or the vault and encrypted
+    /// tokens in one pass. Values which don't follow the placeholder syntax are matched literally
+    /// and only on word boundaries.
+    pub fn deanonymize(&self, input: ReplaceResult) -> String {
+        let text = &input.text;
+        let keyrings: Vec<_> = self
+            .operators
+            .values()
+            .chain(self.stage_operators.values())
+            .filter_map(|o| match o {
+                Operator::Encrypt(keyring) => Some(keyring),
+                _ => None,
+            })
+            .collect();
+        let literals = self.literals(&input.items);
+
+        let mut finders: Vec<TokenFinder> = vec![Box::new(|pos| {
+            let m = self.placeholder.find_at(text, pos)?;
+            let value = match input.items.get(m.as_str()) {
+                Some(v) => Some(v.to_string()),
+                None => self
+                    .vault
+                    .as_ref()
+                    .and_then(|vault| vault.value(m.as_str()).ok().flatten()),
+            };
+            Some((m.start(), m.end(), value))
+        })];
+        // tokens of all keyrings share the syntax, any keyring finds them
+        if let Some(keyring) = keyrings.first() {
+            let keyrings = &keyrings;
+            finders.push(Box::new(move |pos| {
+                let m = keyring.find_at(text, pos)?;
+                let value = keyrings.iter().find_map(|k| k.decrypt(m.as_str()).ok());
+                Some((m.start(), m.end(), value))
+            }));
+        }
+        if let Some(literals) = &literals {
+            finders.push(Box::new(|pos| {
+                let m = literals.find_at(text, pos)?;
+                let value = if is_bounded(text, m.start(), m.end()) {
+                    input.items.get(m.as_str()).cloned()
+                } else {
+                    None
+                };
+                Some((m.start(), m.end(), value))
+            }));
+        }
+        scan_tokens(text, &finders)
+    }
+
+    /// Matcher for `items` keys which are not placeholders, longest keys first.
+    fn literals(&self, items: &HashMap<String, String>) -> Option<Regex> {
+        let mut keys: Vec<&String> = items
+            .keys()
+            .filter(|k| !k.is_empty() && !self.placeholder.is_placeholder(k))
+            .collect();
+        if keys.is_empty() {
+            return None;
+        }
+        keys.sort_by_key(|k| std::cmp::Reverse(k.len()));
+        let pattern = keys
+            .into_iter()
+            .map(|k| regex::escape(k))
+            .collect::<Vec<_>>()
+            .join("|");
+        Regex::new(&pattern).ok()
+    }
+}
+
+/// Parts of the entity outside of the protected regions. Parts are trimmed to an
+/// alphanumeric character where they were cut.
+fn split_protected(text: &str, entity: &Entity, protected: &[(usize, usize)]) -> Vec<Entity> {
+    let cuts: Vec<&(usize, usize)> = protected
+        .iter()
+        .filter(|(s, e)| *s < entity.end && entity.start < *e)
+        .collect();
+    if cuts.is_empty() {
+        return vec![entity.clone()];
+    }
+    let not_alphanumeric = |c: char| !c.is_alphanumeric();
+    let mut parts = vec![];
+    let mut start = entity.start;
+    for &(cut_start, cut_end) in cuts.into_iter().chain([&(entity.end, entity.end)]) {
+        if start < cut_start {
+            let mut part = &text[start..cut_start];
+            if start > entity.start {
+                part = part.trim_start_matches(not_alphanumeric);
+            }
+            let part_start = start + text[start..cut_start].len() - part.len();
+            if cut_start < entity.end {
+                part = part.trim_end_matches(not_alphanumeric);
+            }
+            if !part.is_empty() {
+                parts.push(Entity::new(
+                    text,
+                    &entity.label,
+                    part_start,
+                    part_start + part.len(),
+                    &entity.source,
+                    entity.score,
+                ));
+            }
+        }
+        start = start.max(cut_end);
+    }
+    parts
+}
+
+fn is_bounded(text: &str, start: usize, end: usize) -> bool {
+    let before = text[..start].chars().next_back();
+    let first = text[start..end].chars().next();
+    let last = text[start..end].chars().next_back();
+    let after = text[end..].chars().next();
+    let joined = |a: Option<char>, b: Option<char>| {
+        a.is_some_and(char::is_alphanumeric) && b.is_some_and(char::is_alphanumeric)
+    };
+    !joined(before, first) && !joined(last, after)
+}
@@ -0,0 +1,257 @@
+use crate::anonymizer::validator::mod97;
+use crate::config::{Locale, SurrogateKind};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use sha2::{Digest, Sha256};
+
+const EN_FIRST_NAMES: &[&str] = &[
+    "James",
+    "Mary",
+    "John",
+    "Patricia",
+    "Robert",
+    "Jennifer",
+    "Michael",
+    "Linda",
+    "William",
+    "Elizabeth",
+    "David",
+    "Barbara",
+    "Richard",
+    "Susan",
+    "Joseph",
+    "Jessica",
+    "Thomas",
+    "Sarah",
+    "Charles",
+    "Karen",
+    "Daniel",
+    "Nancy",
+    "Matthew",
+    "Lisa",
+];
+const EN_LAST_NAMES: &[&str] = &[
+    "Smith", "Johnson", "Williams", "Brown", "Jones", "Miller", "Davis", "Wilson", "Anderson",
+    "Taylor", "Thomas", "Moore", "Martin", "Jackson", "Thompson", "White", "Harris", "Clark",
+    "Lewis", "Walker", "Hall", "Allen", "Young", "King",
+];
+const EN_CITIES: &[&str] = &[
+    "London",
+    "Manchester",
+    "Birmingham",
+    "Leeds",
+    "Glasgow",
+    "Liverpool",
+    "Bristol",
+    "Sheffield",
+    "Edinburgh",
+    "Cardiff",
+    "Boston",
+    "Chicago",
+    "Denver",
+    "Seattle",
+    "Portland",
+    "Austin",
+    "Phoenix",
+    "Dallas",
+    "Atlanta",
+    "Houston",
+];
+const PL_FIRST_NAMES: &[&str] = &[
+    "Anna",
+    "Piotr",
+    "Maria",
+    "Krzysztof",
+    "Katarzyna",
+    "Andrzej",
+    "Małgorzata",
+    "Tomasz",
+    "Agnieszka",
+    "Paweł",
+    "Barbara",
+    "Marcin",
+    "Ewa",
+    "Michał",
+    "Magdalena",
+    "Marek",
+    "Joanna",
+    "Grzegorz",
+    "Aleksandra",
+    "Łukasz",
+    "Zofia",
+    "Jakub",
+    "Monika",
+    "Adam",
+];
+const PL_LAST_NAMES: &[&str] = &[
+    "Nowak",
+    "Wójcik",
+    "Kowalczyk",
+    "Woźniak",
+    "Mazur",
+    "Krawczyk",
+    "Kaczmarek",
+    "Zając",
+    "Król",
+    "Wieczorek",
+    "Jabłoński",
+    "Majewski",
+    "Olszewski",
+    "Jaworski",
+    "Malinowski",
+    "Pawlak",
+    "Witkowski",
+    "Walczak",
+    "Stępień",
+    "Górski",
+    "Rutkowski",
+    "Michalak",
+    "Sikora",
+    "Ostrowski",
+];
+const PL_CITIES: &[&str] = &[
+    "Warszawa",
+    "Kraków",
+    "Łódź",
+    "Wrocław",
+    "Poznań",
+    "Gdańsk",
+    "Szczecin",
+    "Bydgoszcz",
+    "Lublin",
+    "Białystok",
+    "Katowice",
+    "Gdynia",
+    "Częstochowa",
+    "Radom",
+    "Toruń",
+    "Kielce",
+    "Rzeszów",
+    "Gliwice",
+    "Olsztyn",
+    "Opole",
+];
+
+/// Realistic fake values replacing detected entities. The same seed, value and attempt
+/// always give the same surrogate, without a seed a random one is drawn per instance.
+#[derive(Debug, Clone)]
+pub struct Surrogate {
+    kind: SurrogateKind,
+    locale: Locale,
+    seed: u64,
+}
+
+impl Surrogate {
+    pub fn new(kind: SurrogateKind, locale: Option<Locale>, seed: Option<u64>) -> Self {
+        Surrogate {
+            kind,
+            locale: locale.unwrap_or_default(),
+            seed: seed.unwrap_or_else(|| OsRng.next_u64()),
+        }
+    }
+
+    /// Surrogate for the value, `attempt` gives alternatives when one is already taken.
+    pub fn generate(&self, value: &str, attempt: usize) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.seed.to_be_bytes());
+        hasher.update(attempt.to_be_bytes());
+        hasher.update(value.as_bytes());
+        let mut numbers = Numbers {
+            digest: hasher.finalize().to_vec(),
+            pos: 0,
+        };
+
+        let (first_names, last_names, cities) = match self.locale {
+            Locale::En => (EN_FIRST_NAMES, EN_LAST_NAMES, EN_CITIES),
+            Locale::Pl => (PL_FIRST_NAMES, PL_LAST_NAMES, PL_CITIES),
+        };
+        match self.kind {
+            SurrogateKind::Name => {
+                format!("{} {}", numbers.pick(first_names), numbers.pick(last_names))
+            }
+            SurrogateKind::FirstName => numbers.pick(first_names).to_string(),
+            SurrogateKind::LastName => numbers.pick(last_names).to_string(),
+            SurrogateKind::City => numbers.pick(cities).to_string(),
+            SurrogateKind::Email => {
+                let first = ascii_lowercase(numbers.pick(first_names));
+                let last = ascii_lowercase(numbers.pick(last_names));
+                let domain = match self.locale {
+                    Locale::En => "example.com",
+                    Locale::Pl => "example.pl",
+                };
+                format!("{first}.{last}{}@{domain}", numbers.digits(2))
+            }
+            SurrogateKind::Phone => match self.locale {
+                Locale::En => format!("+1 555-{}-{}", numbers.digits(3), numbers.digits(4)),
+                Locale::Pl => format!(
+                    "+48 5{} {} {}",
+                    numbers.digits(2),
+                    numbers.digits(3),
+                    numbers.digits(3)
+                ),
+            },
+            SurrogateKind::Iban => match self.locale {
+                Locale::En => iban("GB", &format!("NWBK{}", numbers.digits(14))),
+                Locale::Pl => iban("PL", &numbers.digits(24)),
+            },
+        }
+    }
+
+    /// Surrogate made unique with a number, used when all generated alternatives are taken.
+    pub fn with_suffix(&self, surrogate: &str, n: usize) -> String {
+        match (self.kind, surrogate.split_once('@')) {
+            (SurrogateKind::Email, Some((user, domain))) => format!("{user}{n}@{domain}"),
+            _ => format!("{surrogate} {n}"),
+        }
+    }
+}
+
+struct Numbers {
+    digest: Vec<u8>,
+    pos: usize,
+}
+
+impl Numbers {
+    fn next(&mut self) -> u64 {
+        if self.pos + 8 > self.digest.len() {
+            self.digest = Sha256::digest(&self.digest).to_vec();
+            self.pos = 0;
+        }
+        let bytes: [u8; 8] = self.digest[self.pos..self.pos + 8].try_into().unwrap();
+        self.pos += 8;
+        u64::from_be_bytes(bytes)
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[(self.next() % items.len() as u64) as usize]
+    }
+
+    fn digits(&mut self, count: usize) -> String {
+        (0..count)
+            .map(|_| char::from(b'0' + (self.next() % 10) as u8))
+            .collect()
+    }
+}
+
+fn ascii_lowercase(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            'ą' => 'a',
+            'ć' => 'c',
+            'ę' => 'e',
+            'ł' | 'Ł' => 'l',
+            'ń' => 'n',
+            'ó' => 'o',
+            'ś' | 'Ś' => 's',
+            'ź' | 'ż' | 'Ż' => 'z',
+            c => c.to_ascii_lowercase(),
+        })
+        .collect()
+}
+
+/// IBAN with valid ISO 13616 check digits.
+fn iban(country: &str, bban: &str) -> String {
+    let remainder = mod97(&format!("{bban}{country}00"));
+    format!("{country}{:02}{bban}", 98 - remainder)
+}
@@ -0,0 +1,152 @@
+use crate::config::ValidatorKind;
+use std::net::Ipv6Addr;
+
+/// Checks the check digits of a matched value. Spaces, dashes and dots are ignored.
+pub fn validate(kind: ValidatorKind, value: &str) -> bool {
+    let value = value
+        .chars()
+        .filter(|c| !(c.is_whitespace() || *c == '-' || *c == '.'))
+        .collect::<String>()
+        .to_ascii_uppercase();
+    match kind {
+        ValidatorKind::Luhn => luhn(&value),
+        ValidatorKind::Pesel => pesel(&value),
+        ValidatorKind::Nip => nip(&value),
+        ValidatorKind::Regon => regon(&value),
+        ValidatorKind::Iban => iban(&value),
+        ValidatorKind::IdCard => id_card(&value),
+        ValidatorKind::Passport => passport(&value),
+        ValidatorKind::Ssn => ssn(&value),
+        ValidatorKind::Ipv6 => ipv6(&value),
+    }
+}
+
+/// Payment card numbers.
+pub fn luhn(value: &str) -> bool {
+    let Some(digits) = digits(value) else {
+        return false;
+    };
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, d)| match (i % 2, d * 2) {
+            (1, doubled) if doubled > 9 => doubled - 9,
+            (1, doubled) => doubled,
+            _ => *d,
+        })
+        .sum();
+    digits.len() >= 12 && sum % 10 == 0
+}
+
+/// Polish personal identification number, the birth month is checked too.
+pub fn pesel(value: &str) -> bool {
+    match digits(value) {
+        Some(d) if d.len() == 11 => {
+            let month = (d[2] * 10 + d[3]) % 20;
+            (1..=12).contains(&month)
+                && (10 - weighted_sum(&d, &[1, 3, 7, 9, 1, 3, 7, 9, 1, 3]) % 10) % 10 == d[10]
+        }
+        _ => false,
+    }
+}
+
+/// Polish tax identification number.
+pub fn nip(value: &str) -> bool {
+    match digits(value) {
+        Some(d) if d.len() == 10 => weighted_sum(&d, &[6, 5, 7, 2, 3, 4, 5, 6, 7]) % 11 == d[9],
+        _ => false,
+    }
+}
+
+/// Polish business registry number, 9 or 14 digits.
+pub fn regon(value: &str) -> bool {
+    let weights: &[u32] = match value.len() {
+        9 => &[8, 9, 2, 3, 4, 5, 6, 7],
+        14 => &[2, 4, 8, 5, 0, 9, 7, 3, 6, 1, 2, 4, 8],
+        _ => return false,
+    };
+    match digits(value) {
+        Some(d) => weighted_sum(&d, weights) % 11 % 10 == d[d.len() - 1],
+        None => false,
+    }
+}
+
+/// International bank account number (ISO 13616 mod-97).
+pub fn iban(value: &str) -> bool {
+    (15..=34).contains(&value.len())
+        && value.chars().all(|c| c.is_ascii_alphanumeric())
+        && value[..2].chars().all(|c| c.is_ascii_uppercase())
+        && value[2..4].chars().all(|c| c.is_ascii_digit())
+        && mod97(&format!("{}{}", &value[4..], &value[..4])) == 1
+}
+
+/// Polish identity card number, 3 letters and 6 digits, the first digit is the check digit.
+pub fn id_card(value: &str) -> bool {
+    value.is_ascii()
+        && value.len() == 9
+        && value[..3].chars().all(|c| c.is_ascii_uppercase())
+        && value[3..].chars().all(|c| c.is_ascii_digit())
+        && alphanumeric_sum(value, &[7, 3, 1, 9, 7, 3, 1, 7, 3]) % 10 == 0
+}
+
+/// Polish passport number, 2 letters and 7 digits, the first digit is the check digit.
+pub fn passport(value: &str) -> bool {
+    value.is_ascii()
+        && value.len() == 9
+        && value[..2].chars().all(|c| c.is_ascii_uppercase())
+        && value[2..].chars().all(|c| c.is_ascii_digit())
+        && alphanumeric_sum(value, &[7, 3, 9, 1, 7, 3, 1, 7, 3]) % 10 == 0
+}
+
+/// US social security number, 9 digits.
+pub fn ssn(value: &str) -> bool {
+    match digits(value) {
+        Some(d) if d.len() == 9 => {
+            let area = d[0] * 100 + d[1] * 10 + d[2];
+            area != 0 && area != 666 && area < 900 && d[3..5] != [0, 0] && d[5..] != [0, 0, 0, 0]
+        }
+        _ => false,
+    }
+}
+
+/// IPv6 address with at least two non-zero groups written, so `::1` or a `d::`
+/// of `std::vec` are not reported.
+pub fn ipv6(value: &str) -> bool {
+    value.parse::<Ipv6Addr>().is_ok()
+        && value
+            .split(':')
+            .filter(|group| !group.trim_start_matches('0').is_empty())
+            .count()
+            >= 2
+}
+
+/// Remainder of the number written with digits and letters (`A` = 10, ... `Z` = 35).
+pub(crate) fn mod97(value: &str) -> u32 {
+    value.chars().fold(0, |acc, c| {
+        let value = c.to_digit(36).unwrap_or(0);
+        if value > 9 {
+            (acc * 100 + value) % 97
+        } else {
+            (acc * 10 + value) % 97
+        }
+    })
+}
+
+fn digits(value: &str) -> Option<Vec<u32>> {
+    value.chars().map(|c| c.to_digit(10)).collect()
+}
+
+fn weighted_sum(digits: &[u32], weights: &[u32]) -> u32 {
+    digits.iter().zip(weights).map(|(d, w)| d * w).sum()
+}
+
+/// Weighted sum with letters valued `A` = 10, ... `Z` = 35, the check digit weight
+/// makes a valid number sum up to a multiple of 10.
+fn alphanumeric_sum(value: &str, weights: &[u32]) -> u32 {
+    value
+        .chars()
+        .zip(weights)
+        .map(|(c, w)| c.to_digit(36).unwrap_or(0) * w)
+        .sum()
+}
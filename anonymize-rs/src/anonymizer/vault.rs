@@ -0,0 +1,89 @@
+use anyhow::{anyhow, Result};
+use sled::{CompareAndSwapError, Db, Tree};
+
+/// Persistent value to placeholder mapping, so the same value gets the same pseudonym
+/// across calls, processes and restarts. Mappings are kept separately per namespace.
+#[derive(Debug, Clone)]
+pub struct Vault {
+    db: Db,
+    values: Tree,
+    placeholders: Tree,
+    counters: Tree,
+    sync: bool,
+}
+
+impl Vault {
+    pub fn open(path: &str, namespace: Option<&str>) -> Result<Self> {
+        let db = sled::open(path)?;
+        Self::with_db(db, namespace.unwrap_or("default"))
+    }
+
+    /// Vault of another namespace sharing the same database.
+    pub fn namespace(&self, namespace: &str) -> Result<Self> {
+        let mut vault = Self::with_db(self.db.clone(), namespace)?;
+        vault.sync = self.sync;
+        Ok(vault)
+    }
+
+    fn with_db(db: Db, namespace: &str) -> Result<Self> {
+        Ok(Vault {
+            values: db.open_tree(format!("{namespace}/values"))?,
+            placeholders: db.open_tree(format!("{namespace}/placeholders"))?,
+            counters: db.open_tree(format!("{namespace}/counters"))?,
+            db,
+            sync: false,
+        })
+    }
+
+    /// Flushes the database after every new mapping, otherwise sled flushes it in the
+    /// background every 500 ms.
+    pub fn set_sync(&mut self, sync: bool) {
+        self.sync = sync;
+    }
+
+    pub fn placeholder(&self, value: &str) -> Result<Option<String>> {
+        Self::get(&self.values, value)
+    }
+
+    pub fn value(&self, placeholder: &str) -> Result<Option<String>> {
+        Self::get(&self.placeholders, placeholder)
+    }
+
+    /// Next free index for the label, shared by all users of the vault.
+    pub fn next_index(&self, label: &str) -> Result<usize> {
+        let next = self.counters.update_and_fetch(label, |old| {
+            let current = old.map_or(0, |v| u64::from_be_bytes(v.try_into().unwrap()) + 1);
+            Some(current.to_be_bytes().to_vec())
+        })?;
+        let next = next.ok_or(anyhow!("Vault counter not set"))?;
+        Ok(u64::from_be_bytes(next.as_ref().try_into()?) as usize)
+    }
+
+    /// Stores the mapping unless another placeholder was stored for the value in the
+    /// meantime, returns the placeholder which is stored in the vault.
+    pub fn insert(&self, placeholder: &str, value: &str) -> Result<String> {
+        match self
+            .values
+            .compare_and_swap(value, None as Option<&[u8]>, Some(placeholder))?
+        {
+            Ok(()) => {
+                self.placeholders.insert(placeholder, value)?;
+                if self.sync {
+                    self.db.flush()?;
+                }
+                Ok(placeholder.to_string())
+            }
+            Err(CompareAndSwapError { current, .. }) => {
+                let current = current.ok_or(anyhow!("Vault value removed"))?;
+                Ok(String::from_utf8(current.to_vec())?)
+            }
+        }
+    }
+
+    fn get(tree: &Tree, key: &str) -> Result<Option<String>> {
+        match tree.get(key)? {
+            Some(v) => Ok(Some(String::from_utf8(v.to_vec())?)),
+            None => Ok(None),
+        }
+    }
+}
@@ -16,8 +16,8 @@ enum ReplaceTokensError {
 pub struct ReplaceTokens {}
 
 impl ReplaceTokens {
-    pub fn replace(template: &String) -> Result<String> {
-        let mut text = template.clone();
+    pub fn replace(template: &str) -> Result<String> {
+        let mut text = template.to_string();
         let tokens = Self::find_tokens(template)?;
         for token in tokens {
             let from = format!("${{{}}}", &token);
@@ -30,7 +30,7 @@ impl ReplaceTokens {
         Ok(text)
     }
 
-    fn find_tokens(text: &String) -> Result<Vec<&str>> {
+    fn find_tokens(text: &str) -> Result<Vec<&str>> {
         let re = Regex::new(r"\$\{(?P<token>[a-zA-Z0-9_\-]+)\}").unwrap();
         let tokens: Vec<&str> = re
             .captures_iter(text)
@@ -77,6 +77,145 @@ where
 #[serde(rename_all = "camelCase")]
 pub struct AnonymizePipelineConfig {
     pub pipeline: Vec<AnonymizerConfig>,
+    pub overlap: Option<OverlapStrategy>,
+    pub placeholder: Option<String>,
+    pub operators: Option<HashMap<String, OperatorConfig>>,
+    pub vault: Option<VaultConfig>,
+    /// Entities scored lower are not anonymized.
+    #[serde(rename = "min_score")]
+    pub min_score: Option<f32>,
+}
+
+/// Embedded store keeping placeholders stable between calls and restarts.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultConfig {
+    pub path: String,
+    pub namespace: Option<String>,
+    /// Flush every new mapping to disk before returning, off by default.
+    pub sync: Option<bool>,
+}
+
+/// Operator applied to entities with a given label.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum OperatorConfig {
+    /// Reversible placeholder, the default.
+    Replace,
+    /// Removes the value.
+    Redact,
+    /// Masks letters and digits, optionally keeping the last `keep_last` of them.
+    Mask {
+        masking_char: Option<char>,
+        keep_last: Option<usize>,
+    },
+    /// Salted SHA-256 or HMAC-SHA256 (salt is used as the key) hex digest. Without
+    /// `salt` SHA-256 uses a random salt, so digests differ between pipelines.
+    Hash {
+        algorithm: Option<HashAlgorithm>,
+        salt: Option<String>,
+    },
+    /// Constant value.
+    Custom { value: String },
+    /// AES-256-GCM token, reversible without `items`. `keys` maps key ids to base64
+    /// encoded 32 byte keys, `key_id` selects the key used for new tokens.
+    Encrypt {
+        key_id: String,
+        keys: HashMap<String, String>,
+    },
+    /// FF1 format-preserving encryption of digits, other characters are kept.
+    /// `key` is a base64 encoded 32 byte key.
+    Fpe { key: String, tweak: Option<String> },
+    /// Realistic fake value, mapped back to the original through `items`.
+    Surrogate {
+        kind: SurrogateKind,
+        locale: Option<Locale>,
+        seed: Option<u64>,
+    },
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum SurrogateKind {
+    Name,
+    FirstName,
+    LastName,
+    City,
+    Email,
+    Phone,
+    Iban,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum RegexEngine {
+    /// `regex` crate, linear time matching.
+    #[default]
+    Standard,
+    /// `fancy-regex` crate, adds lookaround and backreferences using backtracking.
+    Fancy,
+}
+
+/// Words expected around matches of a stage.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextConfig {
+    pub words: Vec<String>,
+    /// Number of words searched on each side of a match, 5 by default.
+    pub window: Option<usize>,
+    /// Added to the score of matches with context, 0.35 by default.
+    pub boost: Option<f32>,
+    /// Score of matches without context, they are dropped when it is not set.
+    pub score: Option<f32>,
+}
+
+/// Check digit validation of regex matches.
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum ValidatorKind {
+    /// Payment card numbers.
+    Luhn,
+    Pesel,
+    Nip,
+    /// 9 or 14 digits.
+    Regon,
+    Iban,
+    /// Polish identity card.
+    IdCard,
+    /// Polish passport.
+    Passport,
+    /// US social security number, unassigned areas, groups and serials are rejected.
+    Ssn,
+    Ipv6,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum Locale {
+    #[default]
+    En,
+    Pl,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    HmacSha256,
+}
+
+/// How overlapping entities found by different stages are resolved.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum OverlapStrategy {
+    /// Earlier stage in the pipeline wins.
+    #[default]
+    Priority,
+    /// Longer span wins, ties are resolved by priority.
+    Longest,
+    /// Higher score wins, entities without score are treated as certain.
+    Score,
 }
 
 #[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
@@ -86,20 +225,69 @@ pub enum AnonymizerConfig {
         name: String,
         file: Option<String>,
         keywords: Option<Vec<String>>,
+        /// Dictionary compiled with the `compile` command.
+        dictionary: Option<String>,
+        ignore_case: Option<bool>,
+        nfkc: Option<bool>,
+        fold_diacritics: Option<bool>,
+        context: Option<ContextConfig>,
     },
     Regex {
         name: String,
         file: Option<String>,
         patterns: Option<Vec<String>>,
+        group: Option<usize>,
+        overlap: Option<OverlapStrategy>,
+        validator: Option<ValidatorKind>,
+        invalid_score: Option<f32>,
+        context: Option<ContextConfig>,
+        engine: Option<RegexEngine>,
+        backtrack_limit: Option<usize>,
+        time_limit_ms: Option<u64>,
+        operator: Option<OperatorConfig>,
     },
+    /// Curated regex recognizers shipped with the crate, selected by name and/or locale.
+    Builtin {
+        recognizers: Option<Vec<String>>,
+        locales: Option<Vec<Locale>>,
+        operator: Option<OperatorConfig>,
+    },
+    /// Model files are given with `model_path`, `tokenizer_path` and `id2label`
+    /// or read from a Hugging Face model directory `model_dir`.
     Ner {
-        model_path: String,
-        tokenizer_path: String,
-        id2label: HashMap<String, (String, bool)>,
+        model_path: Option<String>,
+        tokenizer_path: Option<String>,
+        id2label: Option<HashMap<String, (String, bool)>>,
         token_type_ids_included: Option<bool>,
+        model_dir: Option<String>,
+        entities: Option<Vec<String>>,
+        aggregation_strategy: Option<AggregationStrategy>,
+        min_score: Option<f32>,
+        thresholds: Option<HashMap<String, f32>>,
+        rename: Option<HashMap<String, String>>,
+        max_length: Option<usize>,
+        stride: Option<usize>,
+        batch_size: Option<usize>,
     },
 }
 
+/// Grouping of token predictions into entities, as in Hugging Face token classification.
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum AggregationStrategy {
+    /// Every token is a separate entity labeled with its raw tag.
+    #[default]
+    None,
+    /// Consecutive B-/I- tokens of the same type are merged.
+    Simple,
+    /// Words take the tag of their first token.
+    First,
+    /// Words take the tag with the highest average score over their tokens.
+    Average,
+    /// Words take the tag of their highest scoring token.
+    Max,
+}
+
 impl AnonymizePipelineConfig {
     pub async fn new(path: &String) -> Result<AnonymizePipelineConfig> {
         let s = read_config_str(path, Some(true)).await?;
@@ -1,12 +1,13 @@
 use actix_web::middleware::Logger;
 use actix_web::{web, App, HttpResponse, HttpServer, Responder};
-use anonymize_rs::anonymizer::{AnonymizePipeline, Anonymizer, ReplaceResult};
+use anonymize_rs::anonymizer::flashtext_anonymizer::FlashTextAnonymizer;
+use anonymize_rs::anonymizer::normalizer::Normalizer;
+use anonymize_rs::anonymizer::{AnonymizePipeline, Anonymizer, Detector, ReplaceResult};
 use anonymize_rs::config::AnonymizePipelineConfig;
 use anyhow::Result;
 use clap::Parser;
 use models::AnonymizeRequest;
 use std::error::Error;
-use std::io::{self, BufRead};
 
 pub mod anonymizer;
 pub mod config;
@@ -20,6 +21,7 @@ enum AnonymizeCli {
     Server(ServerArgs),
     File(FileArgs),
     Stdin(StdinArgs),
+    Compile(CompileArgs),
 }
 
 #[derive(Debug, clap::Args)]
@@ -40,6 +42,25 @@ struct StdinArgs {
     config: String,
 }
 
+/// Compiles keyword files into a dictionary for `kind: flashText` stages.
+#[derive(Debug, clap::Args)]
+struct CompileArgs {
+    #[arg(long, short = 'i', required = true)]
+    input_file: Vec<String>,
+
+    #[arg(long, short = 'o')]
+    output_file: String,
+
+    #[arg(long)]
+    ignore_case: bool,
+
+    #[arg(long)]
+    nfkc: bool,
+
+    #[arg(long)]
+    fold_diacritics: bool,
+}
+
 #[derive(clap::Args)]
 #[command(author, version, about, long_about = None)]
 struct ServerArgs {
@@ -73,6 +94,22 @@ pub async fn anonymize_get(
     Ok(web::Json(resp))
 }
 
+pub async fn detect_post(
+    anonymize_request: web::Json<AnonymizeRequest>,
+    anonymizer_pipeline: web::Data<AnonymizePipeline>,
+) -> Result<impl Responder, Box<dyn Error>> {
+    let resp = anonymizer_pipeline.detect(&anonymize_request.text)?;
+    Ok(web::Json(resp))
+}
+
+pub async fn detect_get(
+    anonymize_request: web::Query<AnonymizeRequest>,
+    anonymizer_pipeline: web::Data<AnonymizePipeline>,
+) -> Result<impl Responder, Box<dyn Error>> {
+    let resp = anonymizer_pipeline.detect(&anonymize_request.text)?;
+    Ok(web::Json(resp))
+}
+
 pub async fn deanonymize(
     anonymize_request: web::Json<ReplaceResult>,
     anonymizer_pipeline: web::Data<AnonymizePipeline>,
@@ -81,6 +118,17 @@ pub async fn deanonymize(
     Ok(web::Json(resp))
 }
 
+fn compile(args: &CompileArgs) -> Result<()> {
+    let normalizer = Normalizer::new(args.ignore_case, args.nfkc, args.fold_diacritics);
+    let mut anonymizer = FlashTextAnonymizer::with_normalizer(None, normalizer);
+    for input_file in &args.input_file {
+        anonymizer.add_keywords_file(input_file)?;
+    }
+    anonymizer.save_dictionary(&args.output_file)?;
+    println!("COMPILED: {}", args.output_file);
+    Ok(())
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let args = AnonymizeCli::parse();
@@ -99,13 +147,15 @@ async fn main() -> std::io::Result<()> {
                 None => "info".to_string(),
             };
             env_logger::init_from_env(env_logger::Env::new().default_filter_or(log_level));
+            let anonymize_pipeline =
+                web::Data::new(AnonymizePipeline::new(anonymize_config).unwrap());
             HttpServer::new(move || {
                 App::new()
-                    .app_data(web::Data::new(
-                        AnonymizePipeline::new(anonymize_config.clone()).unwrap(),
-                    ))
+                    .app_data(anonymize_pipeline.clone())
                     .route("/api/anonymize", web::post().to(anonymize_post))
                     .route("/api/anonymize", web::get().to(anonymize_get))
+                    .route("/api/detect", web::post().to(detect_post))
+                    .route("/api/detect", web::get().to(detect_get))
                     .route("/api/deanonymize", web::post().to(deanonymize))
                     .wrap(Logger::default())
             })
@@ -118,23 +168,8 @@ async fn main() -> std::io::Result<()> {
         }
         AnonymizeCli::Stdin(_stdin_args) => {
             todo!("FEATURE TO IMPLEMENT");
-            let stdin = io::stdin();
-            let mut lines = stdin.lock().lines();
-
-            while let Some(line) = lines.next() {
-                let length: i32 = line.unwrap().trim().parse().unwrap();
-
-                for _ in 0..length {
-                    let line = lines
-                        .next()
-                        .expect("there was no next line")
-                        .expect("the line could not be read");
-
-                    println!("{}", line);
-                }
-            }
-
-            Ok(())
         }
+        AnonymizeCli::Compile(compile_args) => compile(&compile_args)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string())),
     }
 }
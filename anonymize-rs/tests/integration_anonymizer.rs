@@ -1,8 +1,11 @@
 use anonymize_rs::{
-    anonymizer::{AnonymizePipeline, Anonymizer},
-    config::AnonymizePipelineConfig,
+    anonymizer::{
+        placeholder::Placeholder, resolve_overlaps, AnonymizePipeline, Anonymizer, Detector, Entity,
+    },
+    config::{AnonymizePipelineConfig, AnonymizerConfig, OverlapStrategy},
 };
 use anyhow::Result;
+use std::collections::HashMap;
 
 #[tokio::main]
 #[test]
@@ -45,3 +48,189 @@ async fn test_replace_config() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::main]
+#[test]
+async fn test_overlap_config() -> Result<()> {
+    let path = "./tests/config/config_overlap.yaml".to_string();
+
+    let text = "Card 1234567812345678 and phone 555-123-4567";
+
+    let mut config = AnonymizePipelineConfig::new(&path).await?;
+    assert_eq!(config.overlap, Some(OverlapStrategy::Longest));
+    let anonymize_pipeline = AnonymizePipeline::new(config.clone())?;
+    let entities = anonymize_pipeline.detect(text)?;
+    println!("{:?}", entities);
+    assert_eq!(entities.len(), 2);
+    assert_eq!(entities[0].label, "CARD");
+    assert_eq!(entities[0].text, "1234567812345678");
+
+    let res = anonymize_pipeline.anonymize(text, None, None)?;
+    assert_eq!(res.text, "Card CARD0 and phone PHONE0");
+    assert_eq!(anonymize_pipeline.deanonymize(res), text);
+
+    config.overlap = Some(OverlapStrategy::Priority);
+    let anonymize_pipeline = AnonymizePipeline::new(config)?;
+    let res = anonymize_pipeline.anonymize(text, None, None)?;
+    assert_eq!(res.text, "Card PHONE0345678 and phone PHONE1");
+
+    Ok(())
+}
+
+#[test]
+fn test_resolve_dense_overlaps() {
+    let text = "0123456789".repeat(100);
+    // every 3 char window from stage 0 and every 5 char window from stage 1
+    let candidates = (0..text.len() - 5)
+        .flat_map(|i| {
+            [
+                (0, Entity::new(&text, "A", i, i + 3, "test", None)),
+                (1, Entity::new(&text, "B", i, i + 5, "test", None)),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let priority = resolve_overlaps(candidates.clone(), &OverlapStrategy::Priority);
+    assert_eq!(priority.len(), 332);
+    assert!(priority.iter().all(|e| e.label == "A" && e.start % 3 == 0));
+    assert!(priority.windows(2).all(|w| w[0].end <= w[1].start));
+
+    let longest = resolve_overlaps(candidates, &OverlapStrategy::Longest);
+    assert_eq!(longest.len(), 199);
+    assert!(longest.iter().all(|e| e.label == "B" && e.start % 5 == 0));
+}
+
+#[test]
+fn test_placeholder_deanonymize() -> Result<()> {
+    let items = HashMap::from([
+        ("NAME1".to_string(), "Anna".to_string()),
+        ("NAME10".to_string(), "Jan".to_string()),
+    ]);
+    let placeholder = Placeholder::default();
+    assert_eq!(
+        placeholder.deanonymize("NAME10 and NAME1, NAME2", &items),
+        "Jan and Anna, NAME2"
+    );
+
+    let placeholder = Placeholder::new("[[{label}:{n}]]")?;
+    assert_eq!(placeholder.format("B-PER", 3), "[[B-PER:3]]");
+    let items = HashMap::from([("[[NAME:1]]".to_string(), "Anna".to_string())]);
+    assert_eq!(
+        placeholder.deanonymize("[[NAME:1]] NAME1 [[NAME:10]]", &items),
+        "Anna NAME1 [[NAME:10]]"
+    );
+
+    // known and unknown tokens mixed in a long text
+    let items = (0..5000)
+        .map(|i| (format!("[[NAME:{}]]", 2 * i), format!("name{i}")))
+        .collect::<HashMap<_, _>>();
+    let text = (0..10000)
+        .map(|i| format!("[[NAME:{i}]]"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let expected = (0..10000)
+        .map(|i| match i % 2 {
+            0 => format!("name{}", i / 2),
+            _ => format!("[[NAME:{i}]]"),
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    assert_eq!(placeholder.deanonymize(&text, &items), expected);
+
+    assert!(Placeholder::new("<{label}>").is_err());
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+async fn test_placeholder_config() -> Result<()> {
+    let path = "./tests/config/config_placeholder.yaml".to_string();
+    let config = AnonymizePipelineConfig::new(&path).await?;
+    let anonymize_pipeline = AnonymizePipeline::new(config)?;
+
+    let text = "Codes 1234, 5678 and literal <NUM_0>";
+    let res = anonymize_pipeline.anonymize(text, None, None)?;
+    println!("{:?}", res);
+    assert_eq!(res.text, "Codes <NUM_1>, <NUM_2> and literal <NUM_0>");
+    assert_eq!(anonymize_pipeline.deanonymize(res), text);
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+async fn test_batch_config() -> Result<()> {
+    let path = "./tests/config/config.yaml".to_string();
+    let config = AnonymizePipelineConfig::new(&path).await?;
+    let anonymize_pipeline = AnonymizePipeline::new(config)?;
+
+    let texts = [
+        "I like to eat apples and bananas and plums",
+        "I like plums",
+        "",
+    ];
+    let entities = anonymize_pipeline.detect_batch(&texts)?;
+    assert_eq!(entities.len(), 3);
+    for (text, batch_entities) in texts.iter().zip(&entities) {
+        assert_eq!(&anonymize_pipeline.detect(text)?, batch_entities);
+    }
+
+    let res = anonymize_pipeline.anonymize_batch(&texts, None)?;
+    assert_eq!(
+        res[0].text,
+        "I like to eat FRUIT_FLASH0 and FRUIT_FLASH1 and FRUIT_REGEX0"
+    );
+    assert_eq!(res[1].text, "I like FRUIT_REGEX0");
+    assert_eq!(res[1].items.len(), 1);
+    assert_eq!(res[2].text, "");
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+async fn test_regex_groups_config() -> Result<()> {
+    let path = "./tests/config/config_regex_groups.yaml".to_string();
+    let config = AnonymizePipelineConfig::new(&path).await?;
+    let anonymize_pipeline = AnonymizePipeline::new(config)?;
+
+    let text = "Jan Kowalski <jan@example.pl>, PESEL: 44051401359";
+    let res = anonymize_pipeline.anonymize(text, None, None)?;
+    assert_eq!(res.text, "NAME0 <EMAIL0>, PESEL: PESEL0");
+    assert_eq!(anonymize_pipeline.deanonymize(res), text);
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+async fn test_context_config() -> Result<()> {
+    let path = "./tests/config/config_context.yaml".to_string();
+    let config = AnonymizePipelineConfig::new(&path).await?;
+    let anonymize_pipeline = AnonymizePipeline::new(config)?;
+
+    let text = "Account no. 12345678 of Mrs. Rose, order 87654321 with a rose";
+    let res = anonymize_pipeline.anonymize(text, None, None)?;
+    assert_eq!(
+        res.text,
+        "Account no. ACCOUNT0 of Mrs. NAME0, order 87654321 with a rose"
+    );
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+async fn test_fancy_config() -> Result<()> {
+    let path = "./tests/config/config_fancy.yaml".to_string();
+    let config = AnonymizePipelineConfig::new(&path).await?;
+    let anonymize_pipeline = AnonymizePipeline::new(config.clone())?;
+
+    let text = "PESEL: 44051401359, 02070803628";
+    let res = anonymize_pipeline.anonymize(text, None, None)?;
+    assert_eq!(res.text, "PESEL: PESEL0, 02070803628");
+
+    // limits of the standard engine would be ignored
+    let mut config = config;
+    if let AnonymizerConfig::Regex { engine, .. } = &mut config.pipeline[0] {
+        *engine = None;
+    }
+    assert!(AnonymizePipeline::new(config).is_err());
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use anonymize_rs::anonymizer::builtin::{recognizer, recognizers, RECOGNIZERS};
+use anonymize_rs::anonymizer::{AnonymizePipeline, Anonymizer, Detector};
+use anonymize_rs::config::{AnonymizePipelineConfig, Locale};
+use anyhow::Result;
+
+fn found(name: &str, text: &str) -> Result<Vec<String>> {
+    let anonymizer = recognizer(name).unwrap().anonymizer()?;
+    Ok(anonymizer
+        .detect(text)?
+        .into_iter()
+        .map(|e| e.text)
+        .collect())
+}
+
+#[test]
+fn test_builtin_recognizers() -> Result<()> {
+    let cases = [
+        (
+            "EMAIL",
+            "Mail john.smith+news@mail.example.co.uk or @handle, not a@b",
+            vec!["john.smith+news@mail.example.co.uk"],
+        ),
+        (
+            "URL",
+            "See https://example.com/a?b=1&c=2, http://x.io/path) and www.example.pl.",
+            vec!["https://example.com/a?b=1&c=2", "http://x.io/path", "www.example.pl"],
+        ),
+        (
+            "IPV4",
+            "Hosts 192.168.0.1, 10.0.0.255 but not 256.1.1.1 or 1.2.3",
+            vec!["192.168.0.1", "10.0.0.255"],
+        ),
+        (
+            "IPV6",
+            "Hosts 2001:db8::ff00:42:8329 and FE80::1, not ::1, std::vec or 12:30:45",
+            vec!["2001:db8::ff00:42:8329", "FE80::1"],
+        ),
+        (
+            "IBAN",
+            "Pay to GB82WEST12345698765432 or PL61 1090 1014 0000 0712 1981 2874, not GB82WEST12345698765433",
+            vec!["GB82WEST12345698765432", "PL61 1090 1014 0000 0712 1981 2874"],
+        ),
+        (
+            "CREDIT_CARD",
+            "Card 4111 1111 1111 1111 and 5500-0000-0000-0004, order 1234567890123",
+            vec!["4111 1111 1111 1111", "5500-0000-0000-0004"],
+        ),
+        (
+            "US_SSN",
+            "SSN 123-45-6789, not 000-12-3456, 666-12-3456 or 123-00-6789",
+            vec!["123-45-6789"],
+        ),
+        (
+            "US_PHONE",
+            "Call (555) 123-4567, +1 555-765-4321 or 555.111.2222",
+            vec!["(555) 123-4567", "+1 555-765-4321", "555.111.2222"],
+        ),
+        (
+            "PL_PESEL",
+            "PESEL 44051401359, order 44051401358",
+            vec!["44051401359"],
+        ),
+        (
+            "PL_NIP",
+            "NIP 123-456-32-18, 526-02-50-274 and 1234567890",
+            vec!["123-456-32-18", "526-02-50-274"],
+        ),
+        (
+            "PL_REGON",
+            "REGON 123456785, 12345678512347, not 123456789",
+            vec!["123456785", "12345678512347"],
+        ),
+        (
+            "PL_ID_CARD",
+            "Dowód ABA 300000, not ABA300001",
+            vec!["ABA 300000"],
+        ),
+        (
+            "PL_PASSPORT",
+            "Paszport AA3234567, not AA1234567",
+            vec!["AA3234567"],
+        ),
+        (
+            "PL_PHONE",
+            "Tel. +48 600 700 800, 601-702-803 or +48601702803, not 601702803",
+            vec!["+48 600 700 800", "601-702-803", "+48601702803"],
+        ),
+    ];
+    assert_eq!(cases.len(), RECOGNIZERS.len());
+
+    for (name, text, expected) in cases {
+        assert_eq!(found(name, text)?, expected, "{name}");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_builtin_selection() -> Result<()> {
+    let names = |selected: Vec<&anonymize_rs::anonymizer::builtin::Recognizer>| {
+        selected.into_iter().map(|r| r.name).collect::<Vec<_>>()
+    };
+
+    let all = recognizers(None, None)?;
+    assert_eq!(all.len(), RECOGNIZERS.len());
+
+    let pl = names(recognizers(None, Some(&[Locale::Pl]))?);
+    assert!(pl.contains(&"EMAIL"));
+    assert!(pl.contains(&"PL_PESEL"));
+    assert!(!pl.contains(&"US_SSN"));
+
+    let selected = ["US_SSN".to_string(), "IPV4".to_string()];
+    assert_eq!(
+        names(recognizers(Some(&selected), None)?),
+        vec!["US_SSN", "IPV4"]
+    );
+    assert_eq!(
+        names(recognizers(Some(&selected), Some(&[Locale::Pl]))?),
+        vec!["IPV4"]
+    );
+
+    let err = recognizers(Some(&["SSN".to_string()]), None).unwrap_err();
+    assert!(err.to_string().contains("SSN"));
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+async fn test_builtin_config() -> Result<()> {
+    let path = "./tests/config/config_builtin.yaml".to_string();
+    let config = AnonymizePipelineConfig::new(&path).await?;
+    let anonymize_pipeline = AnonymizePipeline::new(config)?;
+
+    let text = "jan@example.pl, PESEL 44051401359, SSN 123-45-6789, https://example.pl/jan";
+    let res = anonymize_pipeline.anonymize(text, None, None)?;
+    assert_eq!(res.text, "EMAIL0, PESEL PL_PESEL0, SSN 123-45-6789, ");
+    Ok(())
+}
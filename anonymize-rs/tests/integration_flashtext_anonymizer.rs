@@ -1,5 +1,15 @@
-use anonymize_rs::anonymizer::{flashtext_anonymizer::FlashTextAnonymizer, Anonymizer};
+use anonymize_rs::anonymizer::{
+    flashtext_anonymizer::{FlashTextAnonymizer, KeywordMatch},
+    normalizer::Normalizer,
+    Anonymizer, Detector,
+};
+use anonymize_rs::{anonymizer::AnonymizePipeline, config::AnonymizePipelineConfig};
 use anyhow::Result;
+use std::{env, fs, process};
+
+fn keywords(matches: &[KeywordMatch]) -> Vec<&str> {
+    matches.iter().map(|m| m.keyword.as_str()).collect()
+}
 
 #[tokio::main]
 #[test]
@@ -28,7 +38,7 @@ async fn test_flashtext_replace() -> Result<()> {
         let text = test_case.0;
         let keywords = flash_text.find_keywords(text);
         println!("{:?}", keywords);
-        assert_eq!(keywords[0], "apple");
+        assert_eq!(keywords[0].keyword, "apple");
 
         let res = flash_text.replace_keywords(text, Some("FRUIT"), None)?;
         assert_eq!(&res.text, &test_case.1);
@@ -47,7 +57,7 @@ fn test_flashtest_replace_file1() -> Result<()> {
     let text = "I like to eat apples and bananas";
     let keywords = flash_text.find_keywords(text);
     println!("{:?}", keywords);
-    assert_eq!(keywords[0], "apple");
+    assert_eq!(keywords[0].keyword, "apple");
 
     let res = flash_text.replace_keywords(text, Some("FRUIT"), None)?;
     println!("{:?}", res);
@@ -64,7 +74,7 @@ fn test_flashtest_replace_file2() -> Result<()> {
     let text = "I like to eat apples and bananas";
     let keywords = flash_text.find_keywords(text);
     println!("{:?}", keywords);
-    assert_eq!(keywords[0], "apple");
+    assert_eq!(keywords[0].keyword, "apple");
 
     let res = flash_text.replace_keywords(text, None, None)?;
     println!("{:?}", res);
@@ -72,3 +82,275 @@ fn test_flashtest_replace_file2() -> Result<()> {
     assert_eq!(res.text, "I like to eat FRUIT0 and FRUIT1");
     Ok(())
 }
+
+#[test]
+fn test_flashtext_detect() -> Result<()> {
+    let mut flash_text = FlashTextAnonymizer::new(Some("FRUIT".to_string()));
+    flash_text.add_keyword("apple")?;
+    flash_text.add_keyword("plum")?;
+
+    let text = "Zażółć apples and plums";
+    let entities = flash_text.detect(text)?;
+    println!("{:?}", entities);
+
+    assert_eq!(entities.len(), 2);
+    assert_eq!(entities[0].label, "FRUIT");
+    assert_eq!(entities[0].text, "apples");
+    assert_eq!((entities[0].start, entities[0].end), (11, 17));
+    assert_eq!((entities[0].char_start, entities[0].char_end), (7, 13));
+    assert_eq!(entities[1].text, "plums");
+    assert_eq!(entities[1].source, "flashText");
+    assert_eq!(&text[entities[1].start..entities[1].end], "plums");
+    Ok(())
+}
+
+#[test]
+fn test_flashtext_normalize() -> Result<()> {
+    let mut flash_text = FlashTextAnonymizer::with_normalizer(
+        Some("CITY".to_string()),
+        Normalizer::new(true, true, true),
+    );
+    flash_text.add_keyword("Łódź")?;
+    flash_text.add_keyword("KRAKÓW")?;
+    flash_text.add_keyword("straße")?;
+
+    let text = "LODZ, łódź, Lodz, krakow, ｋｒａｋｏｗ, Krako\u{301}w, Strasse, Kraków, Poznań";
+    let entities = flash_text.detect(text)?;
+    let found = entities.iter().map(|e| e.text.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        vec![
+            "LODZ",
+            "łódź",
+            "Lodz",
+            "krakow",
+            "ｋｒａｋｏｗ",
+            "Krako\u{301}w",
+            "Strasse",
+            "Kraków"
+        ]
+    );
+    assert!(entities
+        .iter()
+        .all(|e| &text[e.start..e.end] == e.text.as_str()));
+    assert_eq!(keywords(&flash_text.find_keywords("LODZ")), vec!["lodz"]);
+
+    let res = flash_text.anonymize(text, None, None)?;
+    assert_eq!(
+        res.text,
+        "CITY0, CITY1, CITY2, CITY3, CITY4, CITY5, CITY6, CITY7, Poznań"
+    );
+    assert_eq!(res.items["CITY1"], "łódź");
+    assert_eq!(res.items["CITY4"], "ｋｒａｋｏｗ");
+    assert_eq!(flash_text.deanonymize(res), text);
+
+    let mut exact = FlashTextAnonymizer::new(Some("CITY".to_string()));
+    exact.add_keyword("Łódź")?;
+    assert_eq!(exact.detect("LODZ, Lodz, Łódź")?.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_flashtext_normalize_offsets() -> Result<()> {
+    let normalizer = Normalizer::new(true, true, true);
+    let (normalized, offsets) = normalizer.normalize_with_offsets("Aﬁ Łe\u{301}ß");
+    assert_eq!(normalized, "afi less");
+    assert_eq!(offsets, vec![0, 1, 1, 4, 5, 7, 10, 10, 12]);
+    assert!(Normalizer::default().is_identity());
+    Ok(())
+}
+
+#[test]
+fn test_flashtext_leftmost_longest() -> Result<()> {
+    let mut flash_text = FlashTextAnonymizer::new(Some("PLACE".to_string()));
+    flash_text.add_keywords(["new", "new york", "york", "new york city", "apple"])?;
+
+    let text = "new york, new york city, new yorkers, newer, Anna paid, pineapple, York";
+    assert_eq!(
+        keywords(&flash_text.find_keywords(text)),
+        vec!["new york", "new york city", "new york", "new"]
+    );
+
+    let mut flash_text = FlashTextAnonymizer::new(Some("PLACE".to_string()));
+    flash_text.add_keywords(["new", "new york", "Anna", "new york city", "apple"])?;
+    let entities = flash_text.detect(text)?;
+    let found = entities.iter().map(|e| e.text.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        vec!["new york", "new york city", "new yorkers", "newer", "Anna"]
+    );
+
+    let text = "Zażółć gęślą jaźń, Zażółćmy, zażółć";
+    let mut flash_text = FlashTextAnonymizer::new(Some("WORD".to_string()));
+    flash_text.add_keyword("Zażółć")?;
+    flash_text.add_keyword("jaźń")?;
+    let entities = flash_text.detect(text)?;
+    let found = entities.iter().map(|e| e.text.as_str()).collect::<Vec<_>>();
+    assert_eq!(found, vec!["Zażółć", "jaźń", "Zażółćmy"]);
+    Ok(())
+}
+
+#[test]
+fn test_flashtext_many_keywords() -> Result<()> {
+    let mut flash_text = FlashTextAnonymizer::new(Some("ID".to_string()));
+    flash_text.add_keywords((0..200_000).map(|i| format!("name{i:06}")))?;
+    flash_text.add_keyword("name")?;
+
+    let entities = flash_text.detect("name123456, name, name200000 and name000001")?;
+    let found = entities.iter().map(|e| e.text.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        vec!["name123456", "name", "name200000", "name000001"]
+    );
+    assert_eq!(
+        keywords(&flash_text.find_keywords("name200000 name199999")),
+        vec!["name", "name199999"]
+    );
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+async fn test_flashtext_dictionary() -> Result<()> {
+    let path = env::temp_dir().join(format!("anonymize-rs-{}-cities.dict", process::id()));
+    let path = path.to_string_lossy().to_string();
+
+    let mut compiled = FlashTextAnonymizer::with_normalizer(
+        Some("CITY".to_string()),
+        Normalizer::new(true, false, true),
+    );
+    compiled.add_keywords_file("./tests/config/fruits.txt")?;
+    compiled.add_keywords(["Łódź", "Kraków"])?;
+    compiled.save_dictionary(&path)?;
+
+    let loaded = FlashTextAnonymizer::from_dictionary(Some("CITY".to_string()), &path)?;
+    assert_eq!(loaded.normalizer(), &Normalizer::new(true, false, true));
+    let text = "LODZ, Krakow and apples";
+    assert_eq!(
+        keywords(&loaded.find_keywords(text)),
+        vec!["lodz", "krakow", "apple"]
+    );
+    assert_eq!(loaded.detect(text)?, compiled.detect(text)?);
+
+    env::set_var("ANONYMIZE_TEST_DICTIONARY", &path);
+    let config =
+        AnonymizePipelineConfig::new(&"./tests/config/config_dictionary.yaml".to_string()).await?;
+    env::remove_var("ANONYMIZE_TEST_DICTIONARY");
+    let anonymize_pipeline = AnonymizePipeline::new(config)?;
+    let res = anonymize_pipeline.anonymize("Lodz, Gdańsk, Warsaw", None, None)?;
+    assert_eq!(res.text, "CITY0, CITY1, Warsaw");
+
+    let mut bytes = fs::read(&path)?;
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xff;
+    fs::write(&path, &bytes)?;
+    assert!(FlashTextAnonymizer::from_dictionary(None, &path).is_err());
+
+    bytes[8] = 99;
+    fs::write(&path, &bytes)?;
+    let err = FlashTextAnonymizer::from_dictionary(None, &path).unwrap_err();
+    assert!(err.to_string().contains("version"));
+
+    fs::write(&path, "apple\nbanana\n")?;
+    assert!(FlashTextAnonymizer::from_dictionary(None, &path).is_err());
+    fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn test_flashtext_dictionary_truncated() -> Result<()> {
+    let path = env::temp_dir().join(format!("anonymize-rs-{}-truncated.dict", process::id()));
+    let path = path.to_string_lossy().to_string();
+    let mut flash_text = FlashTextAnonymizer::new(Some("CITY".to_string()));
+    flash_text.add_keyword_with_category("Kraków", "CITY")?;
+    flash_text.add_keywords(["Łódź", "Gdańsk"])?;
+    flash_text.save_dictionary(&path)?;
+    let bytes = fs::read(&path)?;
+
+    for len in [0, 20, 36, 40, bytes.len() - 1] {
+        fs::write(&path, &bytes[..len])?;
+        assert!(FlashTextAnonymizer::from_dictionary(None, &path).is_err());
+    }
+
+    // lengths which overflow or point past the categories, with a valid checksum
+    let mut crafted = bytes.clone();
+    crafted[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+    fs::write(&path, &crafted)?;
+    assert!(FlashTextAnonymizer::from_dictionary(None, &path).is_err());
+
+    let mut crafted = bytes.clone();
+    crafted[36..40].copy_from_slice(&u32::MAX.to_le_bytes());
+    let crc = crc32fast::hash(&crafted[36..]);
+    crafted[32..36].copy_from_slice(&crc.to_le_bytes());
+    fs::write(&path, &crafted)?;
+    let err = FlashTextAnonymizer::from_dictionary(None, &path).unwrap_err();
+    assert!(err.to_string().contains("category length"));
+
+    fs::write(&path, &bytes)?;
+    assert!(FlashTextAnonymizer::from_dictionary(None, &path).is_ok());
+    fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn test_flashtext_categories() -> Result<()> {
+    let mut flash_text = FlashTextAnonymizer::new(Some("FOOD".to_string()));
+    flash_text.add_keywords_file("./tests/config/keywords_categories.txt")?;
+    flash_text.add_keywords_file("./tests/config/keywords_categories.csv")?;
+    flash_text.add_keyword_with_category("Kraków", "CITY")?;
+
+    let text = "Apples: apple, carrots, plums and bananas in New York, Washington, D.C. and Kraków";
+    let matches = flash_text.find_keywords(text);
+    let found = matches
+        .iter()
+        .map(|m| (&text[m.start..m.end], m.category.as_deref()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        vec![
+            ("apple", Some("FRUIT")),
+            ("carrots", Some("VEGETABLE")),
+            ("plums", None),
+            ("bananas", Some("FRUIT")),
+            ("New York", Some("CITY")),
+            ("Washington, D.C.", Some("CITY")),
+            ("Kraków", Some("CITY"))
+        ]
+    );
+    assert_eq!(matches[1].keyword, "carrot");
+
+    let res = flash_text.anonymize(text, None, None)?;
+    assert_eq!(
+        res.text,
+        "Apples: FRUIT0, VEGETABLE0, FOOD0 and FRUIT1 in CITY0, CITY1 and CITY2"
+    );
+    assert_eq!(flash_text.deanonymize(res), text);
+
+    // an explicit replacement wins over the categories
+    let res = flash_text.anonymize(text, Some("ITEM"), None)?;
+    assert_eq!(
+        res.text,
+        "Apples: ITEM0, ITEM1, ITEM2 and ITEM3 in ITEM4, ITEM5 and ITEM6"
+    );
+    assert_eq!(flash_text.deanonymize(res), text);
+
+    // a keyword added again takes the new category
+    flash_text.add_keyword_with_category("plum", "FRUIT")?;
+    flash_text.add_keyword("carrot")?;
+    let labels = flash_text
+        .detect("plum carrot")?
+        .into_iter()
+        .map(|e| e.label)
+        .collect::<Vec<_>>();
+    assert_eq!(labels, vec!["FRUIT", "FOOD"]);
+
+    let path = env::temp_dir().join(format!("anonymize-rs-{}-categories.dict", process::id()));
+    let path = path.to_string_lossy().to_string();
+    flash_text.save_dictionary(&path)?;
+    let loaded = FlashTextAnonymizer::from_dictionary(None, &path)?;
+    assert_eq!(loaded.find_keywords(text), flash_text.find_keywords(text));
+    assert!(loaded.detect("carrot").is_err());
+    assert_eq!(loaded.detect("Kraków")?[0].label, "CITY");
+    fs::remove_file(&path)?;
+    Ok(())
+}
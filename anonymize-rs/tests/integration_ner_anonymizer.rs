@@ -1,8 +1,14 @@
 use anonymize_rs::{
-    anonymizer::ner_anonymizer::NerAnonymizer,
-    config::{AnonymizePipelineConfig, AnonymizerConfig},
+    anonymizer::ner_anonymizer::{
+        aggregate_predictions, filter_scores, id2label_from_config, merge_windows, rename_labels,
+        NerAnonymizer, TokenPrediction,
+    },
+    anonymizer::{replacer::Replacer, AnonymizePipeline, Anonymizer, Entity},
+    config::{AggregationStrategy, AnonymizePipelineConfig, AnonymizerConfig},
 };
 use anyhow::Result;
+use std::collections::HashMap;
+use std::path::Path;
 
 async fn create_anonymizer(model_name: &str, lang: &str) -> Result<NerAnonymizer> {
     let model_path = format!("../examples/{model_name}/model.onnx").to_string();
@@ -15,6 +21,15 @@ async fn create_anonymizer(model_name: &str, lang: &str) -> Result<NerAnonymizer
         tokenizer_path: _,
         id2label,
         token_type_ids_included: _,
+        model_dir: _,
+        entities: _,
+        aggregation_strategy: _,
+        min_score: _,
+        thresholds: _,
+        rename: _,
+        max_length: _,
+        stride: _,
+        batch_size: _,
     } = config.pipeline.last().unwrap()
     {
         id2label
@@ -22,8 +37,12 @@ async fn create_anonymizer(model_name: &str, lang: &str) -> Result<NerAnonymizer
         panic!("WRONG CONFIG");
     };
 
-    let ner_anonymizer =
-        NerAnonymizer::new(model_path, tokenizer_path, id2label.clone(), Some(true))?;
+    let ner_anonymizer = NerAnonymizer::new(
+        model_path,
+        tokenizer_path,
+        id2label.clone().unwrap(),
+        Some(true),
+    )?;
 
     Ok(ner_anonymizer)
 }
@@ -79,3 +98,244 @@ async fn test_ner_replace_en() -> Result<()> {
     }
     Ok(())
 }
+
+#[test]
+fn test_ner_aggregation() -> Result<()> {
+    let id2label: HashMap<String, (String, bool)> = [
+        ("0", "O", false),
+        ("1", "B-PER", true),
+        ("2", "I-PER", true),
+        ("3", "B-LOC", true),
+        ("4", "I-LOC", true),
+    ]
+    .iter()
+    .map(|(i, l, e)| (i.to_string(), (l.to_string(), *e)))
+    .collect();
+
+    // "Jan Kowalski w Krakowie": Kowalski = Kowal + ##ski, Krakowie = Krak + ##owie
+    let token = |start, end, word_id, label: usize, score: f32| {
+        let mut scores = vec![(1.0 - score) / 4.0; 5];
+        scores[label] = score;
+        TokenPrediction {
+            start,
+            end,
+            word_id,
+            scores,
+        }
+    };
+    let text = "Jan Kowalski w Krakowie";
+    let predictions = vec![
+        token(0, 0, None, 0, 0.9),
+        token(0, 3, Some(0), 1, 0.9),
+        token(4, 9, Some(1), 2, 0.8),
+        token(9, 12, Some(1), 0, 0.6),
+        token(13, 14, Some(2), 0, 0.9),
+        token(15, 19, Some(3), 3, 0.7),
+        token(19, 23, Some(3), 4, 0.9),
+        token(0, 0, None, 0, 0.9),
+    ];
+    let spans = |strategy| {
+        aggregate_predictions(text, &predictions, &id2label, &strategy)
+            .into_iter()
+            .map(|e| (e.label, e.text))
+            .collect::<Vec<_>>()
+    };
+    let owned = |v: &[(&str, &str)]| {
+        v.iter()
+            .map(|(l, t)| (l.to_string(), t.to_string()))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        spans(AggregationStrategy::None),
+        owned(&[
+            ("B-PER", "Jan"),
+            ("I-PER", "Kowal"),
+            ("B-LOC", "Krak"),
+            ("I-LOC", "owie")
+        ])
+    );
+    assert_eq!(
+        spans(AggregationStrategy::Simple),
+        owned(&[("PER", "Jan Kowal"), ("LOC", "Krakowie")])
+    );
+    assert_eq!(
+        spans(AggregationStrategy::First),
+        owned(&[("PER", "Jan Kowalski"), ("LOC", "Krakowie")])
+    );
+    assert_eq!(
+        spans(AggregationStrategy::Max),
+        owned(&[("PER", "Jan Kowalski"), ("LOC", "Krakowie"),])
+    );
+    assert_eq!(
+        spans(AggregationStrategy::Average),
+        owned(&[("PER", "Jan Kowalski"), ("LOC", "Krakowie")])
+    );
+
+    let scored = aggregate_predictions(text, &predictions, &id2label, &AggregationStrategy::First);
+    assert!((scored[0].score.unwrap() - 0.85).abs() < 1e-6);
+    assert!((scored[1].score.unwrap() - 0.7).abs() < 1e-6);
+
+    let labels = |min_score, thresholds: &[(&str, f32)]| {
+        let thresholds = thresholds
+            .iter()
+            .map(|(l, t)| (l.to_string(), *t))
+            .collect();
+        filter_scores(scored.clone(), min_score, &thresholds)
+            .into_iter()
+            .map(|e| e.label)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(labels(None, &[]), vec!["PER", "LOC"]);
+    assert_eq!(labels(Some(0.8), &[]), vec!["PER"]);
+    assert_eq!(labels(Some(0.8), &[("LOC", 0.5)]), vec!["PER", "LOC"]);
+    assert_eq!(labels(None, &[("PER", 0.9)]), vec!["LOC"]);
+    Ok(())
+}
+
+#[test]
+fn test_ner_merge_windows() -> Result<()> {
+    let token = |start, end, score: f32| TokenPrediction {
+        start,
+        end,
+        word_id: Some(start as u32),
+        scores: vec![1.0 - score, score],
+    };
+    // windows of 4 tokens overlapping by 2, special tokens have empty offsets
+    let windows = vec![
+        vec![
+            token(0, 0, 0.0),
+            token(0, 1, 0.1),
+            token(2, 3, 0.2),
+            token(4, 5, 0.3),
+            token(6, 7, 0.4),
+            token(0, 0, 0.0),
+        ],
+        vec![
+            token(0, 0, 0.0),
+            token(4, 5, 0.5),
+            token(6, 7, 0.6),
+            token(8, 9, 0.7),
+            token(10, 11, 0.8),
+            token(0, 0, 0.0),
+        ],
+    ];
+    let merged = merge_windows(windows);
+    let tokens = merged
+        .iter()
+        .map(|p| (p.start, p.scores[1]))
+        .collect::<Vec<_>>();
+    // (4, 5) is second in the first window and first in the second one,
+    // (6, 7) is last in the first window and second in the second one
+    assert_eq!(
+        tokens,
+        vec![(0, 0.1), (2, 0.2), (4, 0.3), (6, 0.6), (8, 0.7), (10, 0.8)]
+    );
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+#[ignore]
+async fn test_ner_batch_en() -> Result<()> {
+    let texts = [
+        "My name is Sarah and I live in London",
+        "My name is Sarah and I live in London. I like London.",
+        "Nothing to see here",
+    ];
+    let mut ner_anonymizer = create_anonymizer("dslim", "en").await?;
+    ner_anonymizer.set_batch_size(2);
+    let res = ner_anonymizer.anonymize_batch(&texts, None)?;
+    assert_eq!(res.len(), texts.len());
+    for (text, batch_res) in texts.iter().zip(res) {
+        let res = ner_anonymizer.replace_matches(text, None, None)?;
+        assert_eq!(batch_res.text, res.text);
+        assert_eq!(batch_res.items, res.items);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_ner_id2label_from_config() -> Result<()> {
+    let path = Path::new("./tests/config/ner_model_config.json");
+    let id2label = id2label_from_config(path, None)?;
+    assert_eq!(id2label.len(), 9);
+    assert_eq!(id2label["0"], ("O".to_string(), false));
+    assert_eq!(id2label["3"], ("B-PER".to_string(), true));
+    assert_eq!(id2label["2"], ("I-MISC".to_string(), true));
+
+    let entities = ["PER".to_string(), "B-LOC".to_string()];
+    let id2label = id2label_from_config(path, Some(&entities))?;
+    let anonymized = |id: &str| id2label[id].1;
+    assert!(anonymized("3") && anonymized("4") && anonymized("7"));
+    assert!(!anonymized("0") && !anonymized("1") && !anonymized("5") && !anonymized("8"));
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+#[ignore]
+async fn test_ner_model_dir_en() -> Result<()> {
+    let mut ner_anonymizer =
+        NerAnonymizer::from_dir("../examples/dslim", Some(&["PER".to_string()]))?;
+    ner_anonymizer.set_aggregation_strategy(AggregationStrategy::First);
+    let res =
+        ner_anonymizer.replace_matches("My name is Sarah and I live in London", None, None)?;
+    assert_eq!(res.text, "My name is PER0 and I live in London");
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+async fn test_ner_model_dir_conflict() -> Result<()> {
+    let path = "./tests/config/config_ner_en.yaml".to_string();
+    let mut config = AnonymizePipelineConfig::new(&path).await?;
+    if let AnonymizerConfig::Ner { model_dir, .. } = &mut config.pipeline[0] {
+        *model_dir = Some("../examples/dslim".to_string());
+    }
+    let err = AnonymizePipeline::new(config).err().unwrap();
+    assert!(err.to_string().contains("model_dir"));
+    Ok(())
+}
+
+#[test]
+fn test_ner_rename_labels() -> Result<()> {
+    let text = "Jan Kowalski i Anna";
+    let mut entities = vec![
+        Entity::new(text, "B-nam_liv_person", 0, 3, "ner", Some(0.9)),
+        Entity::new(text, "I-nam_liv_person", 4, 12, "ner", Some(0.9)),
+        Entity::new(text, "nam_liv_person", 15, 19, "ner", Some(0.9)),
+        Entity::new(text, "nam_loc_gpe_city", 15, 19, "ner", Some(0.9)),
+    ];
+    let rename = [("B-nam_liv_person", "FIRST"), ("nam_liv_person", "PERSON")]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    rename_labels(&mut entities, &rename);
+    let labels = entities
+        .iter()
+        .map(|e| e.label.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        labels,
+        vec!["FIRST", "PERSON", "PERSON", "nam_loc_gpe_city"]
+    );
+
+    let res = Replacer::default().replace(text, &entities[1..3], None, None)?;
+    assert_eq!(res.text, "Jan PERSON0 i PERSON1");
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+#[ignore]
+async fn test_ner_replacement_en() -> Result<()> {
+    let ner_anonymizer = create_anonymizer("dslim", "en").await?;
+    let res = ner_anonymizer.replace_matches(
+        "My name is Sarah and I live in London",
+        Some("SECRET"),
+        None,
+    )?;
+    assert_eq!(res.text, "My name is SECRET0 and I live in SECRET1");
+    Ok(())
+}
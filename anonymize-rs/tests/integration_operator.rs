@@ -0,0 +1,420 @@
+use anonymize_rs::{
+    anonymizer::{
+        operator::Operator, placeholder::Placeholder, replacer::Replacer, surrogate::Surrogate,
+        AnonymizePipeline, Anonymizer, Entity, ReplaceResult,
+    },
+    config::{AnonymizePipelineConfig, HashAlgorithm, Locale, OperatorConfig, SurrogateKind},
+};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::env;
+
+#[test]
+fn test_operator_apply() -> Result<()> {
+    let mask = Operator::new(OperatorConfig::Mask {
+        masking_char: Some('#'),
+        keep_last: Some(4),
+    })?;
+    assert_eq!(mask.apply("1234-5678-9012-3456")?, "####-####-####-3456");
+    assert!(!mask.stores_items());
+
+    let mask_all = Operator::new(OperatorConfig::Mask {
+        masking_char: None,
+        keep_last: None,
+    })?;
+    assert_eq!(mask_all.apply("Jan Kowalski")?, "*** ********");
+
+    let sha = Operator::new(OperatorConfig::Hash {
+        algorithm: None,
+        salt: Some(String::new()),
+    })?;
+    assert_eq!(
+        sha.apply("abc")?,
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    let random_salt = || {
+        Operator::new(OperatorConfig::Hash {
+            algorithm: None,
+            salt: None,
+        })
+    };
+    let (first, second) = (random_salt()?, random_salt()?);
+    assert_eq!(first.apply("555-123-4567")?, first.apply("555-123-4567")?);
+    assert_ne!(first.apply("555-123-4567")?, second.apply("555-123-4567")?);
+    assert_ne!(first.apply("abc")?, sha.apply("abc")?);
+
+    let hmac = Operator::new(OperatorConfig::Hash {
+        algorithm: Some(HashAlgorithm::HmacSha256),
+        salt: Some("key".to_string()),
+    })?;
+    assert_eq!(
+        hmac.apply("The quick brown fox jumps over the lazy dog")?,
+        "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+    );
+    assert!(Operator::new(OperatorConfig::Hash {
+        algorithm: Some(HashAlgorithm::HmacSha256),
+        salt: None,
+    })
+    .is_err());
+
+    let custom = Operator::new(OperatorConfig::Custom {
+        value: "<SECRET>".to_string(),
+    })?;
+    assert_eq!(custom.apply("anything")?, "<SECRET>");
+    assert_eq!(Operator::new(OperatorConfig::Redact)?.apply("x")?, "");
+    assert!(Operator::new(OperatorConfig::Replace)?.stores_items());
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+async fn test_operator_config() -> Result<()> {
+    let path = "./tests/config/config_operators.yaml".to_string();
+    let config = AnonymizePipelineConfig::new(&path).await?;
+    let anonymize_pipeline = AnonymizePipeline::new(config)?;
+
+    let text = "Anna, paid with 1234 5678 9012 3456, call 555-123-4567 or mail anna@example.com";
+    let res = anonymize_pipeline.anonymize(text, None, None)?;
+    println!("{:?}", res);
+
+    let email_hash = Operator::new(OperatorConfig::Hash {
+        algorithm: None,
+        salt: Some("pepper".to_string()),
+    })?
+    .apply("anna@example.com")?;
+    assert_eq!(
+        res.text,
+        format!("NAME0, paid with **** **** **** 3456, call  or mail {email_hash}")
+    );
+    assert_eq!(res.items.len(), 1);
+    assert_eq!(res.items["NAME0"], "Anna");
+    Ok(())
+}
+
+#[test]
+fn test_operator_encrypt() -> Result<()> {
+    let keys = HashMap::from([(
+        "k1".to_string(),
+        "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=".to_string(),
+    )]);
+    let encrypt = Operator::new(OperatorConfig::Encrypt {
+        key_id: "k1".to_string(),
+        keys: keys.clone(),
+    })?;
+    assert!(!encrypt.stores_items());
+
+    let token = encrypt.apply("anna@example.com")?;
+    assert!(token.starts_with("<enc:k1:"));
+    assert_ne!(token, encrypt.apply("anna@example.com")?);
+    if let Operator::Encrypt(keyring) = &encrypt {
+        assert_eq!(keyring.decrypt(&token)?, "anna@example.com");
+        let tampered = token.replacen("<enc:k1:", "<enc:k1:A", 1);
+        assert!(keyring.decrypt(&tampered).is_err());
+    }
+
+    assert!(Operator::new(OperatorConfig::Encrypt {
+        key_id: "k2".to_string(),
+        keys: keys.clone(),
+    })
+    .is_err());
+    assert!(Operator::new(OperatorConfig::Encrypt {
+        key_id: "k1".to_string(),
+        keys: HashMap::from([("k1".to_string(), "c2hvcnQ=".to_string())]),
+    })
+    .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_operator_encrypt_many_tokens() -> Result<()> {
+    let keyring = |id: &str, key: &str| {
+        Operator::new(OperatorConfig::Encrypt {
+            key_id: id.to_string(),
+            keys: HashMap::from([(id.to_string(), key.to_string())]),
+        })
+    };
+    let operators = HashMap::from([
+        (
+            "EMAIL".to_string(),
+            keyring("k1", "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=")?,
+        ),
+        (
+            "PHONE".to_string(),
+            keyring("k2", "ZmVkY2JhOTg3NjU0MzIxMGZlZGNiYTk4NzY1NDMyMTA=")?,
+        ),
+    ]);
+    let replacer = Replacer::new(Placeholder::default(), operators.clone(), None);
+
+    let mut text = vec![];
+    let mut expected = vec![];
+    let mut items = HashMap::new();
+    for i in 0..500 {
+        let email = format!("user{i}@example.com");
+        let phone = format!("555-000-{i:04}");
+        text.push(operators["EMAIL"].apply(&email)?);
+        text.push(operators["PHONE"].apply(&phone)?);
+        text.push(format!("NAME{i}"));
+        text.push(format!("city{i}"));
+        items.insert(format!("NAME{i}"), format!("name{i}"));
+        items.insert(format!("city{i}"), format!("Kraków {i}"));
+        expected.extend([email, phone, format!("name{i}"), format!("Kraków {i}")]);
+    }
+    text.push("<enc:k3:AAAA> NAME500".to_string());
+    expected.push("<enc:k3:AAAA> NAME500".to_string());
+
+    let res = ReplaceResult {
+        text: text.join(" "),
+        items,
+    };
+    assert_eq!(replacer.deanonymize(res), expected.join(" "));
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+async fn test_operator_encrypt_config() -> Result<()> {
+    let path = "./tests/config/config_encrypt.yaml".to_string();
+    env::set_var(
+        "ANONYMIZE_TEST_KEY_1",
+        "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=",
+    );
+    env::set_var(
+        "ANONYMIZE_TEST_KEY_2",
+        "ZmVkY2JhOTg3NjU0MzIxMGZlZGNiYTk4NzY1NDMyMTA=",
+    );
+    env::set_var("ANONYMIZE_TEST_KEY_ID", "k1");
+    let old_pipeline = AnonymizePipeline::new(AnonymizePipelineConfig::new(&path).await?)?;
+    env::set_var("ANONYMIZE_TEST_KEY_ID", "k2");
+    let new_pipeline = AnonymizePipeline::new(AnonymizePipelineConfig::new(&path).await?)?;
+    env::remove_var("ANONYMIZE_TEST_KEY_1");
+    env::remove_var("ANONYMIZE_TEST_KEY_2");
+    env::remove_var("ANONYMIZE_TEST_KEY_ID");
+
+    let text = "Anna, mail anna@example.com or anna@example.org";
+    let res = old_pipeline.anonymize(text, None, None)?;
+    println!("{:?}", res);
+    assert!(res.text.starts_with("NAME0, mail <enc:k1:"));
+    assert_eq!(res.items.len(), 1);
+
+    let anonymized = res.text.clone();
+    assert_eq!(new_pipeline.deanonymize(res), text);
+
+    let stateless = new_pipeline.deanonymize(ReplaceResult {
+        text: anonymized,
+        items: HashMap::new(),
+    });
+    assert!(stateless.starts_with("NAME0, mail anna@example.com or anna@example.org"));
+
+    let res = new_pipeline.anonymize(text, None, None)?;
+    assert!(res.text.contains("<enc:k2:"));
+    Ok(())
+}
+
+#[test]
+fn test_operator_fpe() -> Result<()> {
+    let key = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=".to_string();
+    let fpe = Operator::new(OperatorConfig::Fpe {
+        key: key.clone(),
+        tweak: None,
+    })?;
+    assert!(fpe.stores_items());
+
+    let encrypted = fpe.apply("4111-1111-1111-1111")?;
+    println!("{encrypted}");
+    assert_ne!(encrypted, "4111-1111-1111-1111");
+    assert_eq!(encrypted.len(), 19);
+    assert!(encrypted.chars().enumerate().all(|(i, c)| if i % 5 == 4 {
+        c == '-'
+    } else {
+        c.is_ascii_digit()
+    }));
+    assert_eq!(fpe.apply("4111-1111-1111-1111")?, encrypted);
+    if let Operator::Fpe(ff1) = &fpe {
+        assert_eq!(ff1.decrypt(&encrypted)?, "4111-1111-1111-1111");
+    }
+
+    let tweaked = Operator::new(OperatorConfig::Fpe {
+        key,
+        tweak: Some("other".to_string()),
+    })?;
+    assert_ne!(tweaked.apply("4111-1111-1111-1111")?, encrypted);
+    assert!(fpe.apply("1234").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_operator_fpe_short_values() -> Result<()> {
+    let fpe = Operator::new(OperatorConfig::Fpe {
+        key: "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=".to_string(),
+        tweak: None,
+    })?;
+    let replacer = Replacer::new(
+        Placeholder::default(),
+        HashMap::from([("CODE".to_string(), fpe.clone())]),
+        None,
+    );
+
+    let text = "Codes 1234, 12345678 and 12-34";
+    let entities = [(6, 10), (12, 20), (25, 30)]
+        .into_iter()
+        .map(|(start, end)| Entity::new(text, "CODE", start, end, "regex", None))
+        .collect::<Vec<_>>();
+    let res = replacer.replace(text, &entities, None, None)?;
+    let encrypted = fpe.apply("12345678")?;
+    assert_eq!(res.text, format!("Codes CODE0, {encrypted} and CODE1"));
+    assert_eq!(res.items.len(), 3);
+    assert_eq!(replacer.deanonymize(res), text);
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+async fn test_operator_fpe_config() -> Result<()> {
+    let path = "./tests/config/config_fpe.yaml".to_string();
+    let config = AnonymizePipelineConfig::new(&path).await?;
+    let anonymize_pipeline = AnonymizePipeline::new(config)?;
+
+    let text = "Card 4111 1111 1111 1111, phone 555-123-4567 and 555-123-4567.";
+    let res = anonymize_pipeline.anonymize(text, None, None)?;
+    println!("{:?}", res);
+
+    let re = regex::Regex::new(
+        r"^Card \d{4} \d{4} \d{4} \d{4}, phone (\d{3}-\d{3}-\d{4}) and (\d{3}-\d{3}-\d{4})\.$",
+    )?;
+    let captures = re.captures(&res.text).unwrap();
+    assert_eq!(&captures[1], &captures[2]);
+    assert_eq!(res.items.len(), 2);
+    assert_eq!(res.items[&captures[1]], "555-123-4567");
+    assert_eq!(anonymize_pipeline.deanonymize(res), text);
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+async fn test_operator_stage_config() -> Result<()> {
+    let path = "./tests/config/config_stage_operators.yaml".to_string();
+    let config = AnonymizePipelineConfig::new(&path).await?;
+    let anonymize_pipeline = AnonymizePipeline::new(config)?;
+
+    let text = "Paid 4111-1111-1111-1111 by visa, pin 1234, mail anna@example.com";
+    let res = anonymize_pipeline.anonymize(text, None, None)?;
+    println!("{:?}", res);
+
+    // each stage keeps its own operator, the label operator is the fallback
+    let re = regex::Regex::new(r"^Paid (\d{4}-\d{4}-\d{4}-\d{4}) by <CARD>, pin \*\*\*\*, mail $")?;
+    let captures = re.captures(&res.text).unwrap();
+    assert_ne!(&captures[1], "4111-1111-1111-1111");
+    assert_eq!(res.items.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_operator_surrogate() -> Result<()> {
+    let surrogate = |kind, locale, seed| {
+        Operator::new(OperatorConfig::Surrogate {
+            kind,
+            locale: Some(locale),
+            seed,
+        })
+    };
+    let name = surrogate(SurrogateKind::Name, Locale::En, Some(7))?;
+    assert!(name.stores_items());
+    let value = name.apply("John Doe")?;
+    assert_eq!(value.split(' ').count(), 2);
+    assert_eq!(value, name.apply("John Doe")?);
+    assert_eq!(
+        value,
+        surrogate(SurrogateKind::Name, Locale::En, Some(7))?.apply("John Doe")?
+    );
+
+    let phone = surrogate(SurrogateKind::Phone, Locale::Pl, Some(7))?.apply("600 100 200")?;
+    assert!(regex::Regex::new(r"^\+48 5\d{2} \d{3} \d{3}$")?.is_match(&phone));
+
+    let email = surrogate(SurrogateKind::Email, Locale::En, None)?.apply("a@b.com")?;
+    assert!(regex::Regex::new(r"^[a-z]+\.[a-z]+\d{2}@example\.com$")?.is_match(&email));
+
+    for (locale, country, len) in [(Locale::Pl, "PL", 28), (Locale::En, "GB", 22)] {
+        let iban = surrogate(SurrogateKind::Iban, locale, Some(7))?.apply("x")?;
+        assert!(iban.starts_with(country));
+        assert_eq!(iban.len(), len);
+        let rearranged = format!("{}{}", &iban[4..], &iban[..4]);
+        let remainder = rearranged.chars().fold(0u32, |acc, c| {
+            let v = c.to_digit(36).unwrap();
+            (acc * if v > 9 { 100 } else { 10 } + v) % 97
+        });
+        assert_eq!(remainder, 1);
+    }
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+async fn test_operator_surrogate_config() -> Result<()> {
+    let path = "./tests/config/config_surrogate.yaml".to_string();
+    let config = AnonymizePipelineConfig::new(&path).await?;
+    let anonymize_pipeline = AnonymizePipeline::new(config)?;
+
+    let text = "Jan Kowalski (jan@firma.pl) z Gniezno pisze do Adam Mickiewicz. Jan Kowalski";
+    let res = anonymize_pipeline.anonymize(text, None, None)?;
+    println!("{:?}", res);
+
+    assert!(!res.text.contains("Kowalski"));
+    assert!(!res.text.contains("Gniezno"));
+    assert!(!res.text.contains("jan@firma.pl"));
+    assert!(res.text.contains("@example.pl"));
+    assert_eq!(res.items.len(), 4);
+    let person = res
+        .items
+        .iter()
+        .find(|(_, v)| *v == "Jan Kowalski")
+        .map(|(k, _)| k.to_string())
+        .unwrap();
+    assert_eq!(res.text.matches(&person).count(), 2);
+
+    let again = anonymize_pipeline.anonymize(text, None, None)?;
+    assert_eq!(again.text, res.text);
+    assert_eq!(anonymize_pipeline.deanonymize(res), text);
+    Ok(())
+}
+
+#[test]
+fn test_operator_surrogate_exhausted() -> Result<()> {
+    let operators = HashMap::from([(
+        "CITY".to_string(),
+        Operator::new(OperatorConfig::Surrogate {
+            kind: SurrogateKind::City,
+            locale: Some(Locale::Pl),
+            seed: Some(7),
+        })?,
+    )]);
+    let replacer = Replacer::new(Placeholder::default(), operators, None);
+
+    // more distinct values than the surrogate lists have
+    let values = (0..60).map(|i| format!("city{i}")).collect::<Vec<_>>();
+    let text = values.join(", ");
+    let mut entities = vec![];
+    let mut start = 0;
+    for value in &values {
+        entities.push(Entity::new(
+            &text,
+            "CITY",
+            start,
+            start + value.len(),
+            "test",
+            None,
+        ));
+        start += value.len() + 2;
+    }
+
+    let res = replacer.replace(&text, &entities, None, None)?;
+    assert_eq!(res.items.len(), values.len());
+    assert!(res.items.keys().any(|k| k.ends_with(" 2")));
+    assert_eq!(replacer.deanonymize(res), text);
+
+    let surrogate = Surrogate::new(SurrogateKind::Email, Some(Locale::Pl), Some(7));
+    assert_eq!(
+        surrogate.with_suffix("jan.nowak12@example.pl", 3),
+        "jan.nowak123@example.pl"
+    );
+    Ok(())
+}
@@ -1,5 +1,10 @@
-use anonymize_rs::anonymizer::regex_anonymizer::RegexAnonymizer;
+use anonymize_rs::anonymizer::context::Context;
+use anonymize_rs::anonymizer::{regex_anonymizer::RegexAnonymizer, Anonymizer, Detector};
+use anonymize_rs::anonymizer::{replacer::Replacer, resolve_overlaps, Entity};
+use anonymize_rs::config::{ContextConfig, OverlapStrategy, RegexEngine, ValidatorKind};
 use anyhow::Result;
+use std::collections::HashMap;
+use std::time::Duration;
 
 #[test]
 fn test_regex_replace() -> Result<()> {
@@ -76,3 +81,311 @@ fn test_regex_replace_phone() -> Result<()> {
     assert_eq!(res.text, "NUM0 and NUM1 and NUM2 and NUM0");
     Ok(())
 }
+
+#[test]
+fn test_regex_detect() -> Result<()> {
+    let mut regex_anonymizer = RegexAnonymizer::new(Some("NUM".to_string()));
+    regex_anonymizer.add_regex_pattern("\\b[0-9]{10}\\b")?;
+    regex_anonymizer.add_regex_pattern("\\b[0-9]{4}\\b")?;
+
+    let text = "Numer: 1555555555 i 1234";
+    let entities = regex_anonymizer.detect(text)?;
+    println!("{:?}", entities);
+
+    assert_eq!(entities.len(), 2);
+    assert_eq!(entities[0].text, "1555555555");
+    assert_eq!((entities[0].start, entities[0].end), (7, 17));
+    assert_eq!(entities[1].text, "1234");
+    assert_eq!(entities[1].source, "regex");
+    assert_eq!(entities[1].score, None);
+
+    let res = regex_anonymizer.anonymize(text, None, None)?;
+    assert_eq!(res.text, "Numer: NUM0 i NUM1");
+    Ok(())
+}
+
+#[test]
+fn test_regex_protect_placeholders() -> Result<()> {
+    let mut account = RegexAnonymizer::new(Some("ACCOUNT".to_string()));
+    account.add_regex_pattern(r"\b\d{8,16}\b")?;
+    let mut id_number = RegexAnonymizer::new(Some("IDNUMBER".to_string()));
+    id_number.add_regex_pattern(r"\b[A-Z0-9]{7,15}\b")?;
+
+    let text = "Account 1234567812345678 and document ABC12345";
+
+    for (first, second) in [(&account, &id_number), (&id_number, &account)] {
+        let res = first.anonymize(text, None, None)?;
+        let res = second.anonymize(&res.text, None, Some(res.items))?;
+        println!("{:?}", res);
+
+        assert_eq!(res.text, "Account ACCOUNT0 and document IDNUMBER0");
+        assert_eq!(res.items.len(), 2);
+        assert_eq!(account.deanonymize(res), text);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_regex_capture_groups() -> Result<()> {
+    let mut pesel = RegexAnonymizer::new(Some("PESEL".to_string()));
+    pesel.set_group(1);
+    pesel.add_regex_pattern(r"PESEL:\s*(\d{11})")?;
+    assert!(pesel.add_regex_pattern(r"\d{11}").is_err());
+
+    let text = "PESEL: 44051401359, PESEL:02070803628 and 12345678901";
+    let res = pesel.anonymize(text, None, None)?;
+    assert_eq!(res.text, "PESEL: PESEL0, PESEL:PESEL1 and 12345678901");
+    assert_eq!(pesel.deanonymize(res), text);
+
+    // a rejected batch leaves the stored patterns untouched
+    let mut fancy = RegexAnonymizer::new(Some("PESEL".to_string()));
+    fancy.set_engine(RegexEngine::Fancy, None, None);
+    fancy.set_group(1);
+    fancy.add_regex_pattern(r"PESEL:\s*(\d{11})")?;
+    assert!(fancy
+        .add_regex_patterns(&[r"ID:\s*(\d{11})", r"\d{11}"])
+        .is_err());
+    let text = "PESEL: 44051401359, ID: 02070803628";
+    let res = fancy.anonymize(text, None, None)?;
+    assert_eq!(res.text, "PESEL: PESEL0, ID: 02070803628");
+
+    let mut contact = RegexAnonymizer::new(Some("CONTACT".to_string()));
+    contact
+        .add_regex_pattern(r"(?P<NAME>[A-Z][a-z]+ [A-Z][a-z]+)(?: <(?P<EMAIL>[\w.]+@[\w.]+)>)?")?;
+
+    let text = "From: John Smith <john.smith@example.com>, cc: Anna Nowak";
+    let entities = contact.detect(text)?;
+    let found = entities
+        .iter()
+        .map(|e| (e.label.as_str(), e.text.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        vec![
+            ("NAME", "John Smith"),
+            ("EMAIL", "john.smith@example.com"),
+            ("NAME", "Anna Nowak")
+        ]
+    );
+
+    let res = contact.anonymize(text, None, None)?;
+    assert_eq!(res.text, "From: NAME0 <EMAIL0>, cc: NAME1");
+
+    // an explicit replacement wins over the group names
+    let res = contact.anonymize(text, Some("PERSON"), None)?;
+    assert_eq!(res.text, "From: PERSON0 <PERSON1>, cc: PERSON2");
+    assert_eq!(contact.deanonymize(res), text);
+
+    // named groups take precedence over the numbered group
+    let mut mixed = RegexAnonymizer::new(Some("ID".to_string()));
+    mixed.set_group(1);
+    mixed.add_regex_patterns(&[r"(?P<NAME>[A-Z][a-z]+) (\d+)", r"ID: (\d+)"])?;
+    let res = mixed.anonymize("Anna 123, ID: 456", None, None)?;
+    assert_eq!(res.text, "NAME0 123, ID: ID0");
+    Ok(())
+}
+
+#[test]
+fn test_regex_overlapping_patterns() -> Result<()> {
+    let patterns = [r"\b\d{4}\b", r"\b\d{4}-\d{4}\b", r"\bno match\b"];
+    let text = "Codes 1234-5678 and 4321";
+
+    let mut priority = RegexAnonymizer::new(Some("CODE".to_string()));
+    priority.add_regex_patterns(&patterns)?;
+    let found = priority
+        .detect(text)?
+        .into_iter()
+        .map(|e| e.text)
+        .collect::<Vec<_>>();
+    assert_eq!(found, vec!["1234", "5678", "4321"]);
+
+    let mut longest = RegexAnonymizer::new(Some("CODE".to_string()));
+    longest.set_overlap(OverlapStrategy::Longest);
+    longest.add_regex_patterns(&patterns)?;
+    let res = longest.anonymize(text, None, None)?;
+    assert_eq!(res.text, "Codes CODE0 and CODE1");
+    assert_eq!(res.items["CODE0"], "1234-5678");
+    Ok(())
+}
+
+#[test]
+fn test_regex_many_patterns() -> Result<()> {
+    let mut regex_anonymizer = RegexAnonymizer::new(Some("NUM".to_string()));
+    let patterns = (0..100)
+        .map(|i| format!(r"\bID{i:03}-\d+\b"))
+        .collect::<Vec<_>>();
+    regex_anonymizer.add_regex_patterns(&patterns)?;
+
+    let text = "ID042-1 ID007-22 ID100-3 ID099-4";
+    let res = regex_anonymizer.anonymize(text, None, None)?;
+    assert_eq!(res.text, "NUM0 NUM1 ID100-3 NUM2");
+    assert_eq!(regex_anonymizer.deanonymize(res), text);
+    Ok(())
+}
+
+#[test]
+fn test_regex_single_pass() -> Result<()> {
+    let patterns = [r"\d{3}", r"[a-z]+ \d+", r"\b\d+\b", r"\w+", r"é\w*"];
+    let text = "abc 12345 de 678 ab1, déjà 4567890 x";
+
+    for overlap in [
+        OverlapStrategy::Priority,
+        OverlapStrategy::Longest,
+        OverlapStrategy::Score,
+    ] {
+        let mut regex_anonymizer = RegexAnonymizer::new(Some("X".to_string()));
+        regex_anonymizer.set_overlap(overlap.clone());
+        regex_anonymizer.add_regex_patterns(&patterns)?;
+
+        // every pattern searched on its own gives the same candidates
+        let mut candidates = vec![];
+        for (idx, pattern) in patterns.iter().enumerate() {
+            let regex = regex::Regex::new(pattern)?;
+            candidates.extend(regex.find_iter(text).map(|m| {
+                (
+                    idx,
+                    Entity::new(text, "X", m.start(), m.end(), "regex", None),
+                )
+            }));
+        }
+        assert_eq!(
+            regex_anonymizer.detect(text)?,
+            resolve_overlaps(candidates, &overlap)
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_regex_validator() -> Result<()> {
+    let text = "PESEL 44051401359, order 44051401358, PESEL 02270803624";
+
+    let mut pesel = RegexAnonymizer::new(Some("PESEL".to_string()));
+    pesel.set_validator(ValidatorKind::Pesel, None);
+    pesel.add_regex_pattern(r"\b\d{11}\b")?;
+    let res = pesel.anonymize(text, None, None)?;
+    assert_eq!(res.text, "PESEL PESEL0, order 44051401358, PESEL PESEL1");
+
+    pesel.set_validator(ValidatorKind::Pesel, Some(0.3));
+    let scores = pesel
+        .detect(text)?
+        .into_iter()
+        .map(|e| e.score)
+        .collect::<Vec<_>>();
+    assert_eq!(scores, vec![None, Some(0.3), None]);
+    Ok(())
+}
+
+#[test]
+fn test_regex_context() -> Result<()> {
+    let context = |score| ContextConfig {
+        words: vec!["Account".to_string(), "kont".to_string()],
+        window: Some(2),
+        boost: Some(0.3),
+        score,
+    };
+    let text = "Account no. 12345678, numer konta: 11112222, order 87654321 and 33334444 end";
+
+    let mut account = RegexAnonymizer::new(Some("ACCOUNT".to_string()));
+    account.add_regex_pattern(r"\b\d{8,16}\b")?;
+    account.set_context(Context::new(context(None)));
+    let found = account
+        .detect(text)?
+        .into_iter()
+        .map(|e| (e.text, e.score))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        vec![
+            ("12345678".to_string(), Some(1.0)),
+            ("11112222".to_string(), Some(1.0))
+        ]
+    );
+
+    account.set_validator(ValidatorKind::Luhn, Some(0.5));
+    account.set_context(Context::new(context(Some(0.2))));
+    let scores = account
+        .detect(text)?
+        .into_iter()
+        .map(|e| e.score)
+        .collect::<Vec<_>>();
+    assert_eq!(scores, vec![Some(0.8), Some(0.8), Some(0.2), Some(0.2)]);
+
+    // many entities, only the ones right after the context word are kept
+    let text = (0..2000)
+        .map(|i| format!("x x x Account {:08} x x x {:08}", 2 * i, 2 * i + 1))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut account = RegexAnonymizer::new(Some("ACCOUNT".to_string()));
+    account.add_regex_pattern(r"\b\d{8,16}\b")?;
+    account.set_context(Context::new(context(None)));
+    let entities = account.detect(&text)?;
+    assert_eq!(entities.len(), 2000);
+    assert!(entities
+        .iter()
+        .all(|e| e.text.parse::<u32>().unwrap() % 2 == 0));
+    Ok(())
+}
+
+#[test]
+fn test_regex_fancy_engine() -> Result<()> {
+    let mut standard = RegexAnonymizer::new(Some("PESEL".to_string()));
+    assert!(standard.add_regex_pattern(r"(?<=PESEL: )\d{11}").is_err());
+
+    let mut pesel = RegexAnonymizer::new(Some("PESEL".to_string()));
+    pesel.set_engine(RegexEngine::Fancy, None, None);
+    pesel.add_regex_pattern(r"(?<=PESEL: )\d{11}(?!\d)")?;
+    let text = "PESEL: 44051401359, order: 44051401358, PESEL: 440514013591";
+    let res = pesel.anonymize(text, None, None)?;
+    assert_eq!(
+        res.text,
+        "PESEL: PESEL0, order: 44051401358, PESEL: 440514013591"
+    );
+    assert_eq!(pesel.deanonymize(res), text);
+
+    let mut repeated = RegexAnonymizer::new(Some("CODE".to_string()));
+    repeated.set_engine(RegexEngine::Fancy, None, None);
+    repeated.set_group(1);
+    repeated.add_regex_pattern(r"\b(\w+)-\1\b")?;
+    repeated.add_regex_pattern(r"(?P<NAME>[A-Z][a-z]+)(?= said)")?;
+    let res = repeated.anonymize("abc-abc, abc-abd, Anna said", None, None)?;
+    assert_eq!(res.text, "CODE0-abc, abc-abd, NAME0 said");
+    Ok(())
+}
+
+#[test]
+fn test_regex_fancy_limits() -> Result<()> {
+    let text = format!("{}c", "a".repeat(30));
+
+    let mut backtracking = RegexAnonymizer::new(Some("A".to_string()));
+    backtracking.set_engine(RegexEngine::Fancy, Some(10_000), None);
+    backtracking.add_regex_pattern(r"(?:a|aa)+(?=b)")?;
+    assert!(backtracking.detect(&text).is_err());
+
+    let mut slow = RegexAnonymizer::new(Some("A".to_string()));
+    slow.set_engine(RegexEngine::Fancy, None, Some(Duration::ZERO));
+    slow.add_regex_pattern(r"a(?=a)")?;
+    let err = slow.detect(&text).unwrap_err();
+    assert!(err.to_string().contains("time limit"));
+    Ok(())
+}
+
+#[test]
+fn test_replace_around_placeholders() -> Result<()> {
+    let items = HashMap::from([("NAME0".to_string(), "Jan".to_string())]);
+    let text = "NAME0 Kowalski and NAME0, Anna Nowak";
+    let entities = [
+        Entity::new(text, "PER", 0, 14, "ner", Some(0.9)),
+        Entity::new(text, "PER", 19, 36, "ner", Some(0.8)),
+    ];
+
+    let res = Replacer::default().replace(text, &entities, None, Some(items))?;
+    assert_eq!(res.text, "NAME0 PER0 and NAME0, PER1");
+    assert_eq!(res.items["PER0"], "Kowalski");
+    assert_eq!(res.items["PER1"], "Anna Nowak");
+    assert_eq!(
+        Replacer::default().deanonymize(res),
+        "Jan Kowalski and Jan, Anna Nowak"
+    );
+    Ok(())
+}
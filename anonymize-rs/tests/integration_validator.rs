@@ -0,0 +1,47 @@
+use anonymize_rs::anonymizer::validator::{
+    iban, id_card, luhn, nip, passport, pesel, regon, validate,
+};
+use anonymize_rs::config::ValidatorKind;
+
+#[test]
+fn test_validators() {
+    assert!(luhn("4111111111111111"));
+    assert!(!luhn("4111111111111112"));
+    assert!(!luhn("0000"));
+
+    assert!(pesel("44051401359"));
+    assert!(pesel("02270803624"));
+    assert!(!pesel("44051401358"));
+    assert!(!pesel("44151401352"));
+
+    assert!(nip("1234563218"));
+    assert!(!nip("1234567890"));
+
+    assert!(regon("123456785"));
+    assert!(regon("12345678512347"));
+    assert!(!regon("123456789"));
+
+    assert!(iban("GB82WEST12345698765432"));
+    assert!(iban("PL61109010140000071219812874"));
+    assert!(!iban("GB82WEST12345698765433"));
+    assert!(!iban("1234567890123456"));
+
+    assert!(id_card("ABA300000"));
+    assert!(!id_card("ABA300001"));
+    assert!(!id_card("AB1300000"));
+
+    assert!(passport("AA3234567"));
+    assert!(!passport("AA1234567"));
+}
+
+#[test]
+fn test_validate_separators() {
+    assert!(validate(ValidatorKind::Luhn, "4111 1111-1111 1111"));
+    assert!(validate(ValidatorKind::Nip, "123-456-32-18"));
+    assert!(validate(
+        ValidatorKind::Iban,
+        "pl61 1090 1014 0000 0712 1981 2874"
+    ));
+    assert!(validate(ValidatorKind::IdCard, "aba 300000"));
+    assert!(!validate(ValidatorKind::Pesel, "4405140135x"));
+}
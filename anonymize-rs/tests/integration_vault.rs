@@ -0,0 +1,67 @@
+use anonymize_rs::{
+    anonymizer::{vault::Vault, AnonymizePipeline, Anonymizer, ReplaceResult},
+    config::AnonymizePipelineConfig,
+};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::env;
+
+fn vault_path(name: &str) -> String {
+    let path = env::temp_dir().join(format!("anonymize-rs-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    path.to_string_lossy().to_string()
+}
+
+#[test]
+fn test_vault() -> Result<()> {
+    let path = vault_path("vault");
+    let vault = Vault::open(&path, None)?;
+
+    assert_eq!(vault.next_index("NAME")?, 0);
+    assert_eq!(vault.next_index("NAME")?, 1);
+    assert_eq!(vault.next_index("CITY")?, 0);
+
+    assert_eq!(vault.insert("NAME0", "Jan")?, "NAME0");
+    assert_eq!(vault.insert("NAME1", "Jan")?, "NAME0");
+    assert_eq!(vault.placeholder("Jan")?, Some("NAME0".to_string()));
+    assert_eq!(vault.value("NAME0")?, Some("Jan".to_string()));
+    assert_eq!(vault.value("NAME1")?, None);
+
+    let other = vault.namespace("other")?;
+    assert_eq!(other.placeholder("Jan")?, None);
+    assert_eq!(other.next_index("NAME")?, 0);
+
+    // the database is shared instead of reopened, sled holds the file lock until
+    // all handles and background threads of an opened database are gone
+    let vault = vault.namespace("default")?;
+    assert_eq!(vault.placeholder("Jan")?, Some("NAME0".to_string()));
+    assert_eq!(vault.next_index("NAME")?, 2);
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+async fn test_vault_config() -> Result<()> {
+    let config_path = "./tests/config/config_vault.yaml".to_string();
+    env::set_var("ANONYMIZE_TEST_VAULT", vault_path("pipeline"));
+    let config = AnonymizePipelineConfig::new(&config_path).await?;
+    env::remove_var("ANONYMIZE_TEST_VAULT");
+
+    let anonymize_pipeline = AnonymizePipeline::new(config)?;
+    let res = anonymize_pipeline.anonymize("Anna, meet Jan.", None, None)?;
+    assert_eq!(res.text, "NAME0, meet NAME1.");
+    let res = anonymize_pipeline.anonymize("Jan, meet Anna.", None, None)?;
+    assert_eq!(res.text, "NAME1, meet NAME0.");
+
+    // another pipeline sharing the vault, e.g. a server worker
+    let anonymize_pipeline = anonymize_pipeline.clone();
+    let res = anonymize_pipeline.anonymize("Jan, Anna.", None, Some(HashMap::new()))?;
+    assert_eq!(res.text, "NAME1, NAME0.");
+
+    let text = anonymize_pipeline.deanonymize(ReplaceResult {
+        text: "NAME0 and NAME1, not NAME2".to_string(),
+        items: HashMap::new(),
+    });
+    assert_eq!(text, "Anna and Jan, not NAME2");
+    Ok(())
+}
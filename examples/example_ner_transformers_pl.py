@@ -45,3 +45,4 @@ print(onnx_outputs)
 
 
 tokenizer.save_pretrained(output_dir)
+model.config.save_pretrained(output_dir)